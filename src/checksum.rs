@@ -0,0 +1,33 @@
+//! Contains the checksums of files, as returned by the HASH command
+//! described on the draft-bryan-ftpext-hash.
+
+/// The checksum of a file returned by the server on the HASH command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileHash {
+    /// The algorithm used, such as "MD5" or "SHA-256".
+    pub algorithm: String,
+    /// The checksum as an hexadecimal string, in lowercase.
+    pub value: String,
+}
+
+impl FileHash {
+    /// Parse the message of a reply to HASH, such as
+    /// "MD5 0-1024 0123456789abcdef0123456789abcdef file.txt".
+    pub fn parse(message: &str) -> Result<Self, crate::error::Error> {
+        let mut parts = message.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(algorithm), Some(_range), Some(value))
+                if value.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Ok(FileHash {
+                    algorithm: algorithm.to_uppercase(),
+                    value: value.to_lowercase(),
+                })
+            }
+            _ => Err(crate::error::Error::SerializationFailed(format!(
+                "Invalid file hash returned by server: {}.",
+                message
+            ))),
+        }
+    }
+}
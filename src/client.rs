@@ -3,21 +3,59 @@
 //!
 //! Most functions were implemented using the RFC959 as reference
 //! and may not work as expected with deviant server implementations.
+use crate::checksum::FileHash;
+use crate::error::UnexpectedReply;
+use crate::features::Features;
+use crate::listing::{parse_timestamp, Entry, Listing};
+use crate::progress::{Progress, ProgressCallback, ProgressStream};
 use crate::status_code::{StatusCode, StatusCodeKind};
+use crate::stream::Stream;
+use crate::throttle::ThrottledStream;
+use crate::timeout::{with_timeout, TimeoutStream};
 use log::warn;
-use std::net::ToSocketAddrs;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpStream;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::TlsConnector;
+
+/// Run the block of an idempotent command, reconnecting and running it again
+/// when the connection is lost and a reconnect policy is set on the client.
+macro_rules! retrying {
+    ($client:ident, $body:block) => {{
+        let mut attempts = 0;
+        loop {
+            match async { $body }.await {
+                Err(error) if $client.should_reconnect(&error, attempts) => {
+                    $client.reconnect_with_policy(&mut attempts).await?;
+                }
+                result => break result,
+            }
+        }
+    }};
+}
 
 /// Represents a raw server response, with
 /// a status code and the message after it.
 ///
 /// Note that usual FTP responses follow the format:
 /// STATUS_CODE: MESSAGE.
+///
+/// Multi-line responses start with STATUS_CODE-MESSAGE and
+/// end with a line in the usual format, all lines are kept.
 #[derive(Debug, PartialEq)]
 pub struct ServerResponse {
     message: String,
     status_code: StatusCode,
+    lines: Vec<String>,
 }
 
 impl ServerResponse {
@@ -51,22 +89,277 @@ pub enum ClientMode {
     Passive,
     /// The extended passive mode, using the EPSV command
     ExtendedPassive,
-    /// The active mode, not implemented yet
+    /// The active mode, using the PORT or EPRT commands
     Active,
 }
 
+/// The timeouts used by the client, no timeout is applied when None.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timeouts {
+    /// The time allowed to open the control and data connections.
+    pub connect: Option<Duration>,
+    /// The time allowed to receive each reply on the control connection.
+    pub reply: Option<Duration>,
+    /// The time allowed without any data sent or received on a data connection.
+    pub data: Option<Duration>,
+}
+
+/// The policy used to reconnect when the server closes the
+/// control connection, either by replying 421 or by dropping it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    /// The number of reconnection attempts made for each command.
+    pub max_attempts: u32,
+    /// The time waited before each reconnection attempt.
+    pub delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A data connection used by the client transfers, with
+/// the bandwidth limit and inactivity timeout applied.
+type DataStream = ThrottledStream<TimeoutStream<Stream>>;
+
+/// A data connection set up by the client. Passive mode connections
+/// are established right away, while on active mode the client waits
+/// for the server to connect after a transfer command is issued.
+pub enum DataConnection {
+    /// A connection already established with the server.
+    Connected(TcpStream),
+    /// A listener waiting for the server to connect, used on active mode,
+    /// with the address of the server on the control connection.
+    Listening(TcpListener, IpAddr),
+}
+
+impl DataConnection {
+    /// Wait until the data connection with the server is established.
+    ///
+    /// On active mode, only connections from the address of the server are
+    /// accepted, connections from other hosts are closed and the listener
+    /// keeps waiting.
+    pub async fn establish(self) -> Result<TcpStream, crate::error::Error> {
+        match self {
+            DataConnection::Connected(stream) => Ok(stream),
+            DataConnection::Listening(mut listener, server) => loop {
+                let (stream, peer) = listener.accept().await?;
+                if canonical_ip(peer.ip()) == canonical_ip(server) {
+                    return Ok(stream);
+                }
+                warn!(
+                    "Rejected a data connection from {}, expected {}.",
+                    peer, server
+                );
+            },
+        }
+    }
+}
+
+/// A handle to read a file being downloaded, over the live data connection.
+///
+/// The final reply is read when the handle is finished. If it is dropped
+/// instead, the reply is read before the next command sent by the client.
+pub struct DataReader<'a> {
+    client: &'a mut Client,
+    conn: Option<DataStream>,
+}
+
+impl DataReader<'_> {
+    /// Close the data connection and read the final reply to the transfer.
+    ///
+    /// Finishing before the whole file is read interrupts the transfer,
+    /// which most servers report with an error reply.
+    pub async fn finish(mut self) -> Result<(), crate::error::Error> {
+        drop(self.conn.take());
+        self.client
+            .parse_reply_expecting(vec![StatusCodeKind::RequestActionCompleted])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Abort the transfer with ABOR, closing the data connection and
+    /// reading every reply to the transfer and to ABOR.
+    pub async fn abort(mut self) -> Result<(), crate::error::Error> {
+        let conn = self.conn.take();
+        self.client.abort_transfer(conn).await
+    }
+}
+
+impl AsyncRead for DataReader<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut().conn.as_mut() {
+            Some(conn) => Pin::new(conn).poll_read(cx, buf),
+            None => Poll::Ready(Ok(0)),
+        }
+    }
+}
+
+impl Drop for DataReader<'_> {
+    fn drop(&mut self) {
+        if self.conn.is_some() {
+            self.client.pending_replies += 1;
+        }
+    }
+}
+
+/// A handle to write a file being uploaded, over the live data connection.
+///
+/// The final reply is read when the handle is finished. If it is dropped
+/// instead, the reply is read before the next command sent by the client.
+pub struct DataWriter<'a> {
+    client: &'a mut Client,
+    conn: Option<DataStream>,
+}
+
+impl DataWriter<'_> {
+    /// Shutdown the data connection, signaling the end of the file,
+    /// and read the final reply to the transfer.
+    pub async fn finish(mut self) -> Result<(), crate::error::Error> {
+        if let Some(mut conn) = self.conn.take() {
+            if let Err(error) = conn.shutdown().await {
+                self.client.pending_replies += 1;
+                return Err(error.into());
+            }
+        }
+        self.client
+            .parse_reply_expecting(vec![
+                StatusCodeKind::RequestActionCompleted,
+                StatusCodeKind::RequestFileActionCompleted,
+            ])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Abort the transfer with ABOR, closing the data connection and
+    /// reading every reply to the transfer and to ABOR.
+    ///
+    /// Servers may keep the part of the file already uploaded.
+    pub async fn abort(mut self) -> Result<(), crate::error::Error> {
+        let conn = self.conn.take();
+        self.client.abort_transfer(conn).await
+    }
+
+    fn conn(&mut self) -> std::io::Result<&mut DataStream> {
+        self.conn.as_mut().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "The data connection is already closed.",
+            )
+        })
+    }
+}
+
+impl AsyncWrite for DataWriter<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut().conn() {
+            Ok(conn) => Pin::new(conn).poll_write(cx, buf),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut().conn() {
+            Ok(conn) => Pin::new(conn).poll_flush(cx),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut().conn() {
+            Ok(conn) => Pin::new(conn).poll_shutdown(cx),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl Drop for DataWriter<'_> {
+    fn drop(&mut self) {
+        if self.conn.is_some() {
+            self.client.pending_replies += 1;
+        }
+    }
+}
+
 impl ServerResponse {
-    /// Parse a server response from the server text response.
+    /// Parse a server response from the server text response,
+    /// which may span multiple lines.
     pub fn parse(text: &str) -> Self {
         let status_code = StatusCode::parse(text);
-        let message = text[3..].trim().to_string();
+        let code = &text[0..3];
+        let lines: Vec<String> = text
+            .lines()
+            .map(|line| Self::strip_status_code(code, line).to_string())
+            .collect();
+        let message = lines
+            .first()
+            .map(|line| line.trim().to_string())
+            .unwrap_or_default();
 
         Self {
             message,
             status_code,
+            lines,
         }
     }
 
+    /// Remove the "CODE-" or "CODE " prefix from a line of a response.
+    fn strip_status_code<'a>(code: &str, line: &'a str) -> &'a str {
+        let line = line.trim_end_matches('\r');
+        if line.get(0..3) != Some(code) {
+            return line;
+        }
+        match line.get(3..4) {
+            Some("-") | Some(" ") => &line[4..],
+            None => "",
+            _ => line,
+        }
+    }
+
+    /// Get the message after the status code on the first line of the response.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Get the status code of the response.
+    pub fn status_code(&self) -> &StatusCode {
+        &self.status_code
+    }
+
+    /// Get every line of the response, without the status code prefixes.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Get the text of every line of the response, joined by line breaks.
+    pub fn full_message(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| line.trim())
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    /// Returns whether the response spans multiple lines.
+    pub fn is_multiline(&self) -> bool {
+        self.lines.len() > 1
+    }
+
     /// Returns whether the status code returned indicates failure.
     pub fn is_failure_status(&self) -> bool {
         self.status_code.is_failure()
@@ -78,10 +371,30 @@ impl ServerResponse {
 /// commands are issued. This struct is a very thin wrapper over
 /// the FTP protocol.
 pub struct Client {
-    stream: BufReader<TcpStream>,
+    stream: BufReader<Stream>,
     buffer: String,
     welcome_string: Option<String>,
     mode: ClientMode,
+    hostname: String,
+    tls_connector: Option<TlsConnector>,
+    data_protection: bool,
+    active_address: Option<IpAddr>,
+    active_ports: Option<RangeInclusive<u16>>,
+    features: Option<Features>,
+    pending_replies: usize,
+    restart_offset: Option<u64>,
+    progress_callback: Option<ProgressCallback>,
+    download_rate_limit: Option<u64>,
+    upload_rate_limit: Option<u64>,
+    timeouts: Timeouts,
+    keepalive_interval: Option<Duration>,
+    port: u32,
+    implicit_tls: bool,
+    credentials: Option<(String, String)>,
+    transfer_type: Option<&'static str>,
+    directory_changes: Vec<String>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    disconnected: bool,
 }
 
 impl Client {
@@ -90,6 +403,104 @@ impl Client {
         self.mode = mode
     }
 
+    /// Set the local address to listen on for active mode data connections,
+    /// by default the address used by the control connection is used.
+    pub fn set_active_address(&mut self, address: IpAddr) {
+        self.active_address = Some(address)
+    }
+
+    /// Set the range of local ports to listen on for active mode data connections,
+    /// by default any port given by the operating system is used.
+    pub fn set_active_ports(&mut self, ports: RangeInclusive<u16>) {
+        self.active_ports = Some(ports)
+    }
+
+    /// Set a callback to receive the progress of file downloads and uploads,
+    /// such as the ones made by retrieve_file and store. The callback is
+    /// called every time a chunk of data is transferred.
+    ///
+    /// While a callback is set, downloads request the file size with
+    /// SIZE beforehand when the server supports it.
+    pub fn set_progress_callback<F: FnMut(&Progress) + Send + 'static>(&mut self, callback: F) {
+        self.progress_callback = Some(Box::new(callback))
+    }
+
+    /// Stop reporting the progress of transfers.
+    pub fn clear_progress_callback(&mut self) {
+        self.progress_callback = None
+    }
+
+    /// Limit the bandwidth used by both downloads and uploads, in bytes per second.
+    ///
+    /// The limit applies to every transfer made by the client, streaming or not.
+    /// Connections returned by start_transfer are not limited, but can be wrapped
+    /// in a [ThrottledStream](../throttle/struct.ThrottledStream.html).
+    /// A limit of 0 removes the limit.
+    pub fn set_rate_limit(&mut self, bytes_per_second: u64) {
+        self.set_download_rate_limit(bytes_per_second);
+        self.set_upload_rate_limit(bytes_per_second);
+    }
+
+    /// Limit the bandwidth used by downloads, in bytes per second,
+    /// a limit of 0 removes the limit.
+    pub fn set_download_rate_limit(&mut self, bytes_per_second: u64) {
+        self.download_rate_limit = Some(bytes_per_second).filter(|&rate| rate > 0)
+    }
+
+    /// Limit the bandwidth used by uploads, in bytes per second,
+    /// a limit of 0 removes the limit.
+    pub fn set_upload_rate_limit(&mut self, bytes_per_second: u64) {
+        self.upload_rate_limit = Some(bytes_per_second).filter(|&rate| rate > 0)
+    }
+
+    /// Remove the bandwidth limits of downloads and uploads.
+    pub fn clear_rate_limit(&mut self) {
+        self.download_rate_limit = None;
+        self.upload_rate_limit = None;
+    }
+
+    /// Set the timeouts for connections, replies and data transfers.
+    ///
+    /// After a reply times out the control connection may be left in an
+    /// unknown state, so it is better to connect again.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts
+    }
+
+    /// Get the timeouts for connections, replies and data transfers.
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// Send a NOOP on the control connection every interval while a file is
+    /// downloaded or uploaded, such as with retrieve_file and store, so the
+    /// control connection is not dropped for being idle during long transfers.
+    pub fn set_keepalive(&mut self, interval: Duration) {
+        self.keepalive_interval = Some(interval)
+    }
+
+    /// Stop sending NOOP commands during transfers.
+    pub fn clear_keepalive(&mut self) {
+        self.keepalive_interval = None
+    }
+
+    /// Reconnect automatically when the server closes the control connection,
+    /// by replying 421 or dropping it. The client logs in again and restores the
+    /// working directory, transfer type and data channel protection of the session.
+    ///
+    /// Commands sent once the connection is lost reconnect first, but only commands
+    /// without side effects on the server, such as listings, downloads and directory
+    /// changes, are run again after reconnecting when the connection is lost while
+    /// they run. The policy is cleared on logout.
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect_policy = Some(policy)
+    }
+
+    /// Stop reconnecting automatically.
+    pub fn clear_reconnect_policy(&mut self) {
+        self.reconnect_policy = None
+    }
+
     /// Connect to a new FTP server using plain text (no TLS).
     pub async fn connect(
         hostname: &str,
@@ -106,10 +517,216 @@ impl Client {
         user: &str,
         password: &str,
     ) -> Result<Self, crate::error::Error> {
+        Self::connect_with_timeouts(hostname, port, user, password, Timeouts::default()).await
+    }
+
+    /// Connect to a new FTP server using plain text (no TLS) on a specific port,
+    /// using the provided timeouts from the start of the connection.
+    pub async fn connect_with_timeouts(
+        hostname: &str,
+        port: u32,
+        user: &str,
+        password: &str,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        let mut client = Self::open(hostname, port, timeouts).await?;
+        client.login(user, password).await?;
+
+        Ok(client)
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS), the control
+    /// connection is secured before the credentials are sent.
+    ///
+    /// The server certificate is verified against the hostname, which must be a
+    /// DNS name: IP addresses are rejected with a TlsError before connecting.
+    pub async fn connect_secure(
+        hostname: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        Self::connect_secure_with_port(hostname, 21, user, password).await
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS) on a specific port.
+    pub async fn connect_secure_with_port(
+        hostname: &str,
+        port: u32,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let config = Self::default_tls_config()?;
+        Self::connect_secure_with_config(hostname, port, config, user, password).await
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS) on a specific port,
+    /// using the provided TLS configuration. This is useful to trust custom certificates.
+    pub async fn connect_secure_with_config(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let timeouts = Timeouts::default();
+        Self::connect_secure_with_timeouts(hostname, port, config, user, password, timeouts).await
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS) on a specific port,
+    /// using the provided TLS configuration and timeouts.
+    pub async fn connect_secure_with_timeouts(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        // Fail before connecting when the certificate can not be verified.
+        dns_name(hostname)?;
+        let client = Self::open(hostname, port, timeouts).await?;
+        let mut client = client.auth_tls(TlsConnector::from(config)).await?;
+        client.login(user, password).await?;
+        client.protect_data_channel().await?;
+
+        Ok(client)
+    }
+
+    /// Connect to a new FTP server using implicit TLS, the TLS handshake is done
+    /// right after connecting, before the server greeting. Usually on port 990.
+    ///
+    /// The server certificate is verified against the hostname, which must be a
+    /// DNS name: IP addresses are rejected with a TlsError before connecting.
+    pub async fn connect_implicit_secure(
+        hostname: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        Self::connect_implicit_secure_with_port(hostname, 990, user, password).await
+    }
+
+    /// Connect to a new FTP server using implicit TLS on a specific port.
+    pub async fn connect_implicit_secure_with_port(
+        hostname: &str,
+        port: u32,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let config = Self::default_tls_config()?;
+        Self::connect_implicit_secure_with_config(hostname, port, config, user, password).await
+    }
+
+    /// Connect to a new FTP server using implicit TLS on a specific port,
+    /// using the provided TLS configuration. This is useful to trust custom certificates.
+    pub async fn connect_implicit_secure_with_config(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let timeouts = Timeouts::default();
+        Self::connect_implicit_secure_with_timeouts(
+            hostname, port, config, user, password, timeouts,
+        )
+        .await
+    }
+
+    /// Connect to a new FTP server using implicit TLS on a specific port,
+    /// using the provided TLS configuration and timeouts.
+    pub async fn connect_implicit_secure_with_timeouts(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        // Fail before connecting when the certificate can not be verified.
+        dns_name(hostname)?;
+        let connector = TlsConnector::from(config);
+        let mut client = Self::open_implicit_secure(hostname, port, connector, timeouts).await?;
+        client.login(user, password).await?;
+        client.protect_data_channel().await?;
+
+        Ok(client)
+    }
+
+    /// Get the TLS configuration trusting the root certificates of the system.
+    ///
+    /// Every connection secured with a configuration shares its session cache,
+    /// which is how the data connections resume the control connection session.
+    fn default_tls_config() -> Result<Arc<ClientConfig>, crate::error::Error> {
+        let root_store = match rustls_native_certs::load_native_certs() {
+            Ok(root_store) => root_store,
+            // Certificates that could not be parsed are left out.
+            Err((Some(root_store), _)) => root_store,
+            Err((None, error)) => {
+                return Err(crate::error::Error::TlsError(format!(
+                    "Cannot load the root certificates of the system: {}.",
+                    error
+                )))
+            }
+        };
+        let mut config = ClientConfig::new();
+        config.root_store = root_store;
+
+        Ok(Arc::new(config))
+    }
+
+    /// Open the control connection with implicit TLS and read the server greeting,
+    /// without logging in.
+    async fn open_implicit_secure(
+        hostname: &str,
+        port: u32,
+        connector: TlsConnector,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        let raw_stream = Self::connect_tcp(hostname, port, timeouts.connect).await?;
+        let secure_stream = with_timeout(
+            timeouts.connect,
+            "TLS handshake",
+            connector.connect(dns_name(hostname)?, raw_stream),
+        )
+        .await?;
+        let stream = Stream::Secure(Box::new(secure_stream));
+        let mut client = Self::greet(hostname, port, stream, timeouts).await?;
+        client.tls_connector = Some(connector);
+        client.implicit_tls = true;
+
+        Ok(client)
+    }
+
+    /// Open the control connection and read the server greeting, without logging in.
+    async fn open(
+        hostname: &str,
+        port: u32,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        let raw_stream = Self::connect_tcp(hostname, port, timeouts.connect).await?;
+        Self::greet(hostname, port, Stream::Plain(raw_stream), timeouts).await
+    }
+
+    /// Open a TCP connection to the server.
+    async fn connect_tcp(
+        hostname: &str,
+        port: u32,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream, crate::error::Error> {
         let host = format!("{}:{}", hostname, port);
         let addr = host.to_socket_addrs()?.next().unwrap();
-        let raw_stream = TcpStream::connect(&addr).await?;
-        let stream = BufReader::new(raw_stream);
+
+        with_timeout(timeout, "Connection", TcpStream::connect(&addr)).await
+    }
+
+    /// Create a client over an established control connection and read the server greeting.
+    async fn greet(
+        hostname: &str,
+        port: u32,
+        stream: Stream,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        let stream = BufReader::new(stream);
 
         let buffer = String::new();
         let mut client = Client {
@@ -117,16 +734,162 @@ impl Client {
             buffer,
             welcome_string: None,
             mode: ClientMode::ExtendedPassive,
+            hostname: hostname.to_string(),
+            tls_connector: None,
+            data_protection: false,
+            active_address: None,
+            active_ports: None,
+            features: None,
+            pending_replies: 0,
+            restart_offset: None,
+            progress_callback: None,
+            download_rate_limit: None,
+            upload_rate_limit: None,
+            timeouts,
+            keepalive_interval: None,
+            port,
+            implicit_tls: false,
+            credentials: None,
+            transfer_type: None,
+            directory_changes: Vec::new(),
+            reconnect_policy: None,
+            disconnected: false,
         };
         let response = client
             .parse_reply_expecting(vec![StatusCodeKind::ReadyForNewUser])
             .await?;
-        client.welcome_string = Some(response.message);
-        client.login(user, password).await?;
+        client.welcome_string = Some(response.full_message());
 
         Ok(client)
     }
 
+    /// Upgrade the control connection to TLS using the AUTH TLS command.
+    async fn auth_tls(mut self, connector: TlsConnector) -> Result<Self, crate::error::Error> {
+        self.send_command_expecting(
+            "AUTH TLS",
+            vec![StatusCodeKind::SecurityDataExchangeComplete],
+        )
+        .await?;
+
+        let raw_stream = match self.stream.into_inner() {
+            Stream::Plain(raw_stream) => raw_stream,
+            Stream::Secure(_) => {
+                return Err(crate::error::Error::UnexpectedStatusCode(
+                    "Control connection is already secured.".to_string(),
+                ))
+            }
+        };
+        let handshake = connector.connect(dns_name(&self.hostname)?, raw_stream);
+        let secure_stream = with_timeout(self.timeouts.connect, "TLS handshake", handshake).await?;
+        self.stream = BufReader::new(Stream::Secure(Box::new(secure_stream)));
+        self.tls_connector = Some(connector);
+
+        Ok(self)
+    }
+
+    /// Open a new control connection to the server, logging in again and restoring
+    /// the working directory, transfer type and data channel protection of the session.
+    /// The client mode and other settings are kept as they are.
+    pub async fn reconnect(&mut self) -> Result<(), crate::error::Error> {
+        let hostname = self.hostname.clone();
+        let client = match self.tls_connector.clone() {
+            Some(connector) if self.implicit_tls => {
+                Self::open_implicit_secure(&hostname, self.port, connector, self.timeouts).await?
+            }
+            Some(connector) => {
+                let client = Self::open(&hostname, self.port, self.timeouts).await?;
+                client.auth_tls(connector).await?
+            }
+            None => Self::open(&hostname, self.port, self.timeouts).await?,
+        };
+        self.stream = client.stream;
+        self.welcome_string = client.welcome_string;
+        self.disconnected = false;
+        self.pending_replies = 0;
+        self.restart_offset = None;
+
+        // The session is restored without reconnecting again on failure,
+        // which is left to the reconnect policy.
+        if let Some((user, password)) = self.credentials.clone() {
+            self.authenticate(&user, &password).await?;
+        }
+        if self.data_protection {
+            self.send_command_expecting("PBSZ 0", vec![StatusCodeKind::Ok])
+                .await?;
+            self.send_command_expecting("PROT P", vec![StatusCodeKind::Ok])
+                .await?;
+        }
+        if let Some(transfer_type) = self.transfer_type {
+            let cmd = format!("TYPE {}", transfer_type);
+            self.send_command_expecting(&cmd, vec![StatusCodeKind::Ok])
+                .await?;
+        }
+        for cmd in self.directory_changes.clone() {
+            self.send_command_expecting(&cmd, vec![StatusCodeKind::RequestFileActionCompleted])
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a command failed because the connection was lost
+    /// and may be run again after reconnecting.
+    fn should_reconnect(&self, error: &crate::error::Error, attempts: u32) -> bool {
+        let policy = match self.reconnect_policy {
+            Some(policy) => policy,
+            None => return false,
+        };
+        let connection_lost = match error {
+            crate::error::Error::IoError(error) => is_connection_lost(error),
+            _ => self.disconnected,
+        };
+
+        connection_lost && attempts < policy.max_attempts
+    }
+
+    /// Reconnect following the reconnect policy, counting the attempts
+    /// made and waiting the policy delay before each one.
+    async fn reconnect_with_policy(
+        &mut self,
+        attempts: &mut u32,
+    ) -> Result<(), crate::error::Error> {
+        let policy = self.reconnect_policy.unwrap_or_default();
+        loop {
+            *attempts += 1;
+            tokio::time::delay_for(policy.delay).await;
+            match self.reconnect().await {
+                Ok(()) => return Ok(()),
+                Err(error) if *attempts >= policy.max_attempts => return Err(error),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Protect the data connections with TLS, using the PBSZ and PROT commands
+    /// described on RFC4217. This is done automatically for secure connections.
+    ///
+    /// The data connections resume the TLS session of the control connection,
+    /// as required by servers like vsftpd (require_ssl_reuse) and FileZilla Server.
+    pub async fn protect_data_channel(&mut self) -> Result<(), crate::error::Error> {
+        self.write_unary_command_expecting("PBSZ", "0", vec![StatusCodeKind::Ok])
+            .await?;
+        self.write_unary_command_expecting("PROT", "P", vec![StatusCodeKind::Ok])
+            .await?;
+        self.data_protection = true;
+
+        Ok(())
+    }
+
+    /// Stop protecting the data connections with TLS, data will be sent as plain text
+    /// while the control connection stays secure.
+    pub async fn clear_data_channel(&mut self) -> Result<(), crate::error::Error> {
+        self.write_unary_command_expecting("PROT", "C", vec![StatusCodeKind::Ok])
+            .await?;
+        self.data_protection = false;
+
+        Ok(())
+    }
+
     /// Get the welcome message sent by the server at the connection establishment.
     pub fn get_welcome(&self) -> Option<&String> {
         self.welcome_string.as_ref()
@@ -136,16 +899,36 @@ impl Client {
     /// Note that many servers require a login with an anonymous user,
     /// such as client.login("anonymous", "anonymous@mail.com").
     pub async fn login(&mut self, user: &str, password: &str) -> Result<(), crate::error::Error> {
-        self.write_unary_command_expecting("USER", user, vec![StatusCodeKind::PasswordRequired])
+        self.reconnect_if_disconnected().await?;
+        self.authenticate(user, password).await
+    }
+
+    /// Send the USER and PASS commands, without reconnecting first.
+    async fn authenticate(
+        &mut self,
+        user: &str,
+        password: &str,
+    ) -> Result<(), crate::error::Error> {
+        let user_cmd = format!("USER {}", user);
+        self.send_command_expecting(&user_cmd, vec![StatusCodeKind::PasswordRequired])
             .await?;
-        self.write_unary_command_expecting("PASS", password, vec![StatusCodeKind::UserLoggedIn])
+        let password_cmd = format!("PASS {}", password);
+        self.send_command_expecting(&password_cmd, vec![StatusCodeKind::UserLoggedIn])
             .await?;
+        // Servers may advertise different features to logged users.
+        self.features = None;
+        self.credentials = Some((user.to_string(), password.to_string()));
 
         Ok(())
     }
 
     /// Logout from the current user/password pair.
+    ///
+    /// The server closes the connection on QUIT, so the credentials and the reconnect
+    /// policy are cleared, keeping the next commands from logging in again.
     pub async fn logout(&mut self) -> Result<(), crate::error::Error> {
+        self.credentials = None;
+        self.reconnect_policy = None;
         self.write_command_expecting("QUIT", vec![StatusCodeKind::ClosingControlConnection])
             .await?;
 
@@ -154,22 +937,32 @@ impl Client {
 
     /// Change the working directory on the current session.
     pub async fn cwd(&mut self, dir: &str) -> Result<(), crate::error::Error> {
-        self.write_unary_command_expecting(
-            "CWD",
-            dir,
-            vec![StatusCodeKind::RequestFileActionCompleted],
-        )
-        .await?;
+        retrying!(self, {
+            self.write_unary_command_expecting(
+                "CWD",
+                dir,
+                vec![StatusCodeKind::RequestFileActionCompleted],
+            )
+            .await?;
+            // An absolute path does not depend on the previous directory changes.
+            if dir.starts_with('/') {
+                self.directory_changes.clear();
+            }
+            self.directory_changes.push(format!("CWD {}", dir));
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Go up to the parent directory on the current session.
     pub async fn cdup(&mut self) -> Result<(), crate::error::Error> {
-        self.write_command_expecting("CDUP", vec![StatusCodeKind::RequestFileActionCompleted])
-            .await?;
+        retrying!(self, {
+            self.write_command_expecting("CDUP", vec![StatusCodeKind::RequestFileActionCompleted])
+                .await?;
+            self.directory_changes.push("CDUP".to_string());
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Show server information regarding its implementation status
@@ -191,26 +984,58 @@ impl Client {
         Ok(())
     }
 
+    /// Get the features supported by the server, using the FEAT command.
+    ///
+    /// The features are requested once and cached on the client, servers
+    /// that do not implement FEAT are treated as having no features.
+    pub async fn features(&mut self) -> Result<&Features, crate::error::Error> {
+        if self.features.is_none() {
+            self.write_command("FEAT").await?;
+            let response = self.parse_reply().await?;
+            let features = if response.is_failure_status() {
+                Features::default()
+            } else {
+                Features::parse(&response)
+            };
+            self.features = Some(features);
+        }
+
+        Ok(self.features.get_or_insert_with(Features::default))
+    }
+
+    /// Get the features supported by the server, if they were already requested.
+    pub fn cached_features(&self) -> Option<&Features> {
+        self.features.as_ref()
+    }
+
     /// This command should not do anything other than receiving
     /// an OK response from the server.
     pub async fn noop(&mut self) -> Result<(), crate::error::Error> {
-        self.write_command_expecting("NOOP", vec![StatusCodeKind::Ok])
-            .await?;
-        Ok(())
+        retrying!(self, {
+            self.write_command_expecting("NOOP", vec![StatusCodeKind::Ok])
+                .await?;
+            Ok(())
+        })
     }
 
     /// Set the transfer type to ascii
     pub async fn ascii(&mut self) -> Result<(), crate::error::Error> {
-        self.write_unary_command_expecting("TYPE", "A", vec![StatusCodeKind::Ok])
-            .await?;
-        Ok(())
+        retrying!(self, {
+            self.write_unary_command_expecting("TYPE", "A", vec![StatusCodeKind::Ok])
+                .await?;
+            self.transfer_type = Some("A");
+            Ok(())
+        })
     }
 
     /// Set the transfer type to binary
     pub async fn binary(&mut self) -> Result<(), crate::error::Error> {
-        self.write_unary_command_expecting("TYPE", "I", vec![StatusCodeKind::Ok])
-            .await?;
-        Ok(())
+        retrying!(self, {
+            self.write_unary_command_expecting("TYPE", "I", vec![StatusCodeKind::Ok])
+                .await?;
+            self.transfer_type = Some("I");
+            Ok(())
+        })
     }
 
     /// Get the current reported status from the server. This can be used
@@ -218,88 +1043,156 @@ impl Client {
     /// and argument to get behaviour similar to LIST, this particular
     /// behaviour is not implemented.
     pub async fn status(&mut self) -> Result<String, crate::error::Error> {
-        let response = self
-            .write_command_expecting("STAT", vec![StatusCodeKind::SystemStatus])
-            .await?;
+        retrying!(self, {
+            let response = self
+                .write_command_expecting("STAT", vec![StatusCodeKind::SystemStatus])
+                .await?;
 
-        Ok(response.message)
+            Ok(response.full_message())
+        })
     }
 
     /// List the provided path in any way the server desires.
     pub async fn list(&mut self, path: &str) -> Result<String, crate::error::Error> {
-        let mut conn = self.get_data_connection().await?;
-        self.write_unary_command_expecting(
-            "LIST",
-            path,
-            vec![
-                StatusCodeKind::TransferStarted,
-                StatusCodeKind::TransferAboutToStart,
-            ],
-        )
-        .await?;
-
-        let mut buffer = Vec::with_capacity(1024);
-        conn.read_to_end(&mut buffer).await?;
-        self.parse_reply_expecting(vec![StatusCodeKind::RequestActionCompleted])
-            .await?;
-        let text = String::from_utf8(buffer).map_err(|_| {
-            crate::error::Error::SerializationFailed(
-                "Invalid ASCII returned on server directory listing.".to_string(),
-            )
-        })?;
-        Ok(text)
+        retrying!(self, {
+            let buffer = self.read_listing(&format!("LIST {}", path)).await?;
+            let text = String::from_utf8(buffer).map_err(|_| {
+                crate::error::Error::SerializationFailed(
+                    "Invalid ASCII returned on server directory listing.".to_string(),
+                )
+            })?;
+            Ok(text)
+        })
     }
 
     /// List the provided path, providing only name information about files and directories.
     pub async fn list_names(&mut self, path: &str) -> Result<Vec<String>, crate::error::Error> {
-        let mut conn = self.get_data_connection().await?;
-        self.write_unary_command_expecting(
-            "NLST",
-            path,
-            vec![
-                StatusCodeKind::TransferStarted,
-                StatusCodeKind::TransferAboutToStart,
-            ],
-        )
-        .await?;
+        retrying!(self, {
+            let buffer = self.read_listing(&format!("NLST {}", path)).await?;
+            let text = String::from_utf8(buffer).map_err(|_| {
+                crate::error::Error::SerializationFailed(
+                    "Invalid ASCII returned on server directory name listing.".to_string(),
+                )
+            })?;
+            Ok(text.lines().map(|line| line.to_owned()).collect())
+        })
+    }
 
+    /// Issue a listing command and read its whole data connection, along with the final reply.
+    async fn read_listing(&mut self, cmd: &str) -> Result<Vec<u8>, crate::error::Error> {
+        let conn = self.start_transfer(cmd).await?;
         let mut buffer = Vec::with_capacity(1024);
-        conn.read_to_end(&mut buffer).await?;
+        let result = {
+            let mut conn = TimeoutStream::new(conn, self.timeouts.data);
+            conn.read_to_end(&mut buffer).await.map_err(Into::into)
+        };
+        self.transfer_result(result, 0)?;
         self.parse_reply_expecting(vec![StatusCodeKind::RequestActionCompleted])
             .await?;
-        let text = String::from_utf8(buffer).map_err(|_| {
-            crate::error::Error::SerializationFailed(
-                "Invalid ASCII returned on server directory name listing.".to_string(),
-            )
-        })?;
-        Ok(text.lines().map(|line| line.to_owned()).collect())
+
+        Ok(buffer)
+    }
+
+    /// List the provided path with LIST and parse the output into entries, this works
+    /// with servers that do not support MLSD, as long as they use the Unix or DOS format.
+    ///
+    /// Lines that can not be parsed are kept on the listing, instead of being dropped.
+    pub async fn list_entries(&mut self, path: &str) -> Result<Listing, crate::error::Error> {
+        let text = self.list(path).await?;
+        Ok(Listing::parse(&text))
+    }
+
+    /// List the provided path using MLSD, getting structured information
+    /// about each file and directory, as described on RFC3659.
+    ///
+    /// An empty path lists the current working directory. Some servers
+    /// also list the directory itself and its parent, check the entry kind.
+    pub async fn mlsd(&mut self, path: &str) -> Result<Vec<Entry>, crate::error::Error> {
+        retrying!(self, {
+            let cmd = if path.is_empty() {
+                "MLSD".to_string()
+            } else {
+                format!("MLSD {}", path)
+            };
+            let buffer = self.read_listing(&cmd).await?;
+            let text = String::from_utf8(buffer).map_err(|_| {
+                crate::error::Error::SerializationFailed(
+                    "Invalid UTF-8 returned on server machine listing.".to_string(),
+                )
+            })?;
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(Entry::parse_mlsx)
+                .collect()
+        })
+    }
+
+    /// Get structured information about a single file or directory using MLST,
+    /// as described on RFC3659. The entry name is the full path sent by the server.
+    pub async fn mlst(&mut self, path: &str) -> Result<Entry, crate::error::Error> {
+        retrying!(self, {
+            let response = if path.is_empty() {
+                self.write_command_expecting(
+                    "MLST",
+                    vec![StatusCodeKind::RequestFileActionCompleted],
+                )
+                .await?
+            } else {
+                self.write_unary_command_expecting(
+                    "MLST",
+                    path,
+                    vec![StatusCodeKind::RequestFileActionCompleted],
+                )
+                .await?
+            };
+
+            // The entry is sent on the line after the header, starting with a space.
+            let line = response.lines().get(1).ok_or_else(|| {
+                crate::error::Error::SerializationFailed(format!(
+                    "Missing entry on server machine listing: {}.",
+                    response.full_message()
+                ))
+            })?;
+            Entry::parse_mlsx(line.strip_prefix(' ').unwrap_or(line))
+        })
     }
 
     /// Store a new file on a provided path and name.
     pub async fn store<B: AsRef<[u8]>>(
         &mut self,
         path: &str,
-        data: B,
-    ) -> Result<(), crate::error::Error> {
-        // Scope connection so it drops before reading server reply.
-        {
-            let mut conn = self.get_data_connection().await?;
-            self.write_unary_command_expecting(
-                "STOR",
-                path,
-                vec![
-                    StatusCodeKind::TransferStarted,
-                    StatusCodeKind::TransferAboutToStart,
-                ],
+        data: B,
+    ) -> Result<(), crate::error::Error> {
+        let data = data.as_ref();
+        self.upload_from(
+            &format!("STOR {}", path),
+            data,
+            Some(data.len() as u64),
+            vec![StatusCodeKind::RequestActionCompleted],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Store a new file on a provided path and name, reading its contents from a reader.
+    /// The data is copied in chunks so it is never fully loaded in memory.
+    /// Returns the number of bytes uploaded.
+    pub async fn store_from<R: AsyncRead + Unpin>(
+        &mut self,
+        path: &str,
+        reader: R,
+    ) -> Result<u64, crate::error::Error> {
+        let (uploaded, _reply) = self
+            .upload_from(
+                &format!("STOR {}", path),
+                reader,
+                None,
+                vec![StatusCodeKind::RequestActionCompleted],
             )
             .await?;
-            conn.write_all(data.as_ref()).await?;
-        }
-
-        self.parse_reply_expecting(vec![StatusCodeKind::RequestActionCompleted])
-            .await?;
 
-        Ok(())
+        Ok(uploaded)
     }
 
     /// Store a new file on a provided path using a random unique name.
@@ -307,22 +1200,32 @@ impl Client {
         &mut self,
         data: B,
     ) -> Result<String, crate::error::Error> {
-        // Scope connection so it drops before reading server reply.
-        {
-            let mut conn = self.get_data_connection().await?;
-            self.write_command_expecting(
+        let data = data.as_ref();
+        let (_uploaded, reply) = self
+            .upload_from(
                 "STOU",
-                vec![
-                    StatusCodeKind::TransferStarted,
-                    StatusCodeKind::TransferAboutToStart,
-                ],
+                data,
+                Some(data.len() as u64),
+                vec![StatusCodeKind::RequestActionCompleted],
             )
             .await?;
-            conn.write_all(data.as_ref()).await?;
-        }
 
-        let reply = self
-            .parse_reply_expecting(vec![StatusCodeKind::RequestActionCompleted])
+        Ok(reply.message)
+    }
+
+    /// Store a new file on a provided path using a random unique name,
+    /// reading its contents from a reader.
+    pub async fn store_unique_from<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: R,
+    ) -> Result<String, crate::error::Error> {
+        let (_uploaded, reply) = self
+            .upload_from(
+                "STOU",
+                reader,
+                None,
+                vec![StatusCodeKind::RequestActionCompleted],
+            )
             .await?;
 
         Ok(reply.message)
@@ -334,38 +1237,244 @@ impl Client {
         path: &str,
         data: B,
     ) -> Result<(), crate::error::Error> {
-        // Scope connection so it drops before reading server reply.
-        {
-            let mut conn = self.get_data_connection().await?;
-            self.write_unary_command_expecting(
-                "APPE",
-                path,
+        let data = data.as_ref();
+        self.upload_from(
+            &format!("APPE {}", path),
+            data,
+            Some(data.len() as u64),
+            vec![
+                StatusCodeKind::RequestActionCompleted,
+                StatusCodeKind::RequestFileActionCompleted,
+            ],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Append to a existing file or a create a new one, reading the data from a reader.
+    /// Returns the number of bytes uploaded.
+    pub async fn append_from<R: AsyncRead + Unpin>(
+        &mut self,
+        path: &str,
+        reader: R,
+    ) -> Result<u64, crate::error::Error> {
+        let (uploaded, _reply) = self
+            .upload_from(
+                &format!("APPE {}", path),
+                reader,
+                None,
                 vec![
-                    StatusCodeKind::TransferStarted,
-                    StatusCodeKind::TransferAboutToStart,
+                    StatusCodeKind::RequestActionCompleted,
+                    StatusCodeKind::RequestFileActionCompleted,
                 ],
             )
             .await?;
-            conn.write_all(data.as_ref()).await?;
-        }
 
-        self.parse_reply_expecting(vec![
-            StatusCodeKind::RequestActionCompleted,
-            StatusCodeKind::RequestFileActionCompleted,
-        ])
-        .await?;
+        Ok(uploaded)
+    }
+
+    /// Issue an upload command and copy a reader into its data connection,
+    /// returning the number of bytes uploaded and the final server reply.
+    async fn upload_from<R: AsyncRead + Unpin>(
+        &mut self,
+        cmd: &str,
+        mut reader: R,
+        length: Option<u64>,
+        valid_statuses: Vec<StatusCodeKind>,
+    ) -> Result<(u64, ServerResponse), crate::error::Error> {
+        let offset = self.restart_offset.unwrap_or(0);
+        let conn = self.start_transfer(cmd).await?;
+        // Scope connection so it drops before reading server reply.
+        let (result, keepalives) = {
+            let total = length.map(|length| offset + length);
+            let conn = self.data_stream(conn, self.upload_rate_limit);
+            let callback = self.progress_callback.as_mut();
+            let mut conn = ProgressStream::new(conn, callback, offset, total);
+            let transfer = async {
+                let uploaded = tokio::io::copy(&mut reader, &mut conn).await?;
+                conn.shutdown().await?;
+                Ok(uploaded)
+            };
+            Self::keep_alive(&mut self.stream, self.keepalive_interval, transfer).await
+        };
+
+        let uploaded = self.transfer_result(result, keepalives)?;
+        let reply = self
+            .parse_transfer_reply(keepalives, valid_statuses)
+            .await?;
+        Ok((uploaded, reply))
+    }
+
+    /// Restart a file transfer at a byte offset, using the REST command.
+    /// The offset applies to the next transfer command, such as RETR.
+    ///
+    /// REST is sent right before the transfer command, after the data
+    /// connection is set up, as some servers reset the offset on PASV or PORT.
+    /// Offsets are only meaningful on binary mode.
+    pub async fn restart(&mut self, offset: u64) -> Result<(), crate::error::Error> {
+        self.restart_offset = Some(offset);
 
         Ok(())
     }
 
-    /// Restart a file transfer. Unimplemented.
-    pub fn restart(&mut self) -> Result<(), crate::error::Error> {
-        unimplemented!();
+    /// Download a file at a path into a byte buffer, starting at a byte offset.
+    ///
+    /// The offset is validated against the file size when the server supports SIZE.
+    pub async fn retrieve_file_from(
+        &mut self,
+        path: &str,
+        offset: u64,
+    ) -> Result<Vec<u8>, crate::error::Error> {
+        retrying!(self, {
+            let size = self.validate_restart_offset(path, offset).await?;
+            if size == Some(offset) {
+                return Ok(Vec::new());
+            }
+            self.restart(offset).await?;
+
+            let mut buffer = Vec::with_capacity(1024);
+            self.download(path, size, &mut buffer).await?;
+            Ok(buffer)
+        })
     }
 
-    /// Abort a file transfer. Unimplemented.
-    pub fn abort(&mut self) -> Result<(), crate::error::Error> {
-        unimplemented!();
+    /// Resume the download of a file into a local file, appending the bytes
+    /// missing locally, the local file is created if it does not exist.
+    /// Returns the number of bytes downloaded.
+    ///
+    /// The local size is validated against the file size when the server supports SIZE.
+    pub async fn resume_download<P: AsRef<Path>>(
+        &mut self,
+        path: &str,
+        local_path: P,
+    ) -> Result<u64, crate::error::Error> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(local_path)
+            .await?;
+        let offset = file.metadata().await?.len();
+        let size = self.validate_restart_offset(path, offset).await?;
+        if size == Some(offset) {
+            return Ok(0);
+        }
+
+        self.restart(offset).await?;
+        self.download(path, size, file).await
+    }
+
+    /// Check that an offset is not past the end of a file, returning the
+    /// file size if the server supports SIZE.
+    async fn validate_restart_offset(
+        &mut self,
+        path: &str,
+        offset: u64,
+    ) -> Result<Option<u64>, crate::error::Error> {
+        if !self.features().await?.size {
+            return Ok(None);
+        }
+
+        let size = self.size(path).await?;
+        if offset > size {
+            return Err(crate::error::Error::InvalidRestartOffset(format!(
+                "Offset {} is past the end of {}, which has {} bytes.",
+                offset, path, size
+            )));
+        }
+
+        Ok(Some(size))
+    }
+
+    /// Abort a file transfer in progress on a data connection, such as the one
+    /// returned by [start_transfer](struct.Client.html#method.start_transfer).
+    ///
+    /// The Telnet IP and Synch signals are sent before ABOR, as described on RFC959,
+    /// so the server handles the command while busy with the transfer. On secure
+    /// connections only ABOR is sent, since urgent data can not be sent over TLS.
+    /// The data connection is closed and every reply to the transfer and to ABOR
+    /// is read, leaving the client ready for new commands.
+    pub async fn abort(&mut self, data_connection: Stream) -> Result<(), crate::error::Error> {
+        self.abort_transfer(data_connection).await
+    }
+
+    /// Abort the transfer in progress on any kind of data connection.
+    async fn abort_transfer<C>(&mut self, data_connection: C) -> Result<(), crate::error::Error> {
+        if !self.stream.get_ref().is_secure() {
+            self.write_telnet_synch().await?;
+        }
+        self.write_command("ABOR").await?;
+        drop(data_connection);
+
+        // The server replies 426 and 226 if the transfer was aborted, 226 if it
+        // had completed or may reply to both the transfer and ABOR. A NOOP marks
+        // the end of these replies, since none of them use the same status code.
+        self.write_command("NOOP").await?;
+        let mut aborted = false;
+        loop {
+            let response = self.parse_reply().await?;
+            match response.status_code.kind {
+                StatusCodeKind::Ok => break,
+                StatusCodeKind::RequestActionCompleted | StatusCodeKind::DataConnectionOpen => {
+                    aborted = true
+                }
+                _ => {}
+            }
+        }
+
+        if aborted {
+            Ok(())
+        } else {
+            Err(crate::error::Error::UnexpectedStatusCode(format!(
+                "Server did not confirm the transfer abort, expected {:?}.",
+                vec![
+                    StatusCodeKind::RequestActionCompleted,
+                    StatusCodeKind::DataConnectionOpen,
+                ]
+            )))
+        }
+    }
+
+    /// Write the Telnet IP (interrupt process) and Synch signals, the Synch
+    /// is a Telnet DM (data mark) sent as TCP urgent data.
+    async fn write_telnet_synch(&mut self) -> Result<(), crate::error::Error> {
+        const IAC: u8 = 255;
+        const IP: u8 = 244;
+        const DM: u8 = 242;
+
+        self.stream.get_mut().write_all(&[IAC, IP, IAC]).await?;
+        self.stream.get_mut().flush().await?;
+        self.write_urgent_byte(DM).await
+    }
+
+    #[cfg(unix)]
+    async fn write_urgent_byte(&mut self, byte: u8) -> Result<(), crate::error::Error> {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = self.stream.get_ref().get_ref().as_raw_fd();
+        // Safety: the file descriptor is owned by the control connection, which
+        // outlives this call, and the buffer is valid for one byte.
+        let sent = unsafe {
+            libc::send(
+                fd,
+                &byte as *const u8 as *const libc::c_void,
+                1,
+                libc::MSG_OOB,
+            )
+        };
+        if sent < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    async fn write_urgent_byte(&mut self, byte: u8) -> Result<(), crate::error::Error> {
+        // Urgent data is not supported on this platform, send it as regular data.
+        self.stream.get_mut().write_all(&[byte]).await?;
+
+        Ok(())
     }
 
     /// Preallocate space on the server. Unimplemented.
@@ -419,10 +1528,12 @@ impl Client {
 
     /// Get the current working directory.
     pub async fn pwd(&mut self) -> Result<String, crate::error::Error> {
-        let response = self
-            .write_command_expecting("PWD", vec![StatusCodeKind::PathCreated])
-            .await?;
-        Ok(response.message)
+        retrying!(self, {
+            let response = self
+                .write_command_expecting("PWD", vec![StatusCodeKind::PathCreated])
+                .await?;
+            Ok(response.message)
+        })
     }
 
     /// This command is used by the server to provide services
@@ -455,11 +1566,13 @@ impl Client {
 
     /// Get the type of operating system on the server.
     pub async fn system(&mut self) -> Result<String, crate::error::Error> {
-        let response = self
-            .write_command_expecting("SYST", vec![StatusCodeKind::NameSystemType])
-            .await?;
+        retrying!(self, {
+            let response = self
+                .write_command_expecting("SYST", vec![StatusCodeKind::NameSystemType])
+                .await?;
 
-        Ok(response.message)
+            Ok(response.message)
+        })
     }
 
     /// Delete a file at a path.
@@ -474,33 +1587,340 @@ impl Client {
         Ok(())
     }
 
+    /// Get the size of a file in bytes, using the SIZE command.
+    ///
+    /// Note that the size reported on ASCII mode may differ from the
+    /// size of the file on disk, use binary mode for exact sizes.
+    pub async fn size(&mut self, path: &str) -> Result<u64, crate::error::Error> {
+        retrying!(self, {
+            let response = self
+                .write_unary_command_expecting("SIZE", path, vec![StatusCodeKind::FileStatus])
+                .await?;
+
+            response.message.parse().map_err(|_| {
+                crate::error::Error::SerializationFailed(format!(
+                    "Invalid file size returned by server: {}.",
+                    response.message
+                ))
+            })
+        })
+    }
+
+    /// Get the last modification time of a file, using the MDTM command.
+    pub async fn modified(&mut self, path: &str) -> Result<SystemTime, crate::error::Error> {
+        retrying!(self, {
+            let response = self
+                .write_unary_command_expecting("MDTM", path, vec![StatusCodeKind::FileStatus])
+                .await?;
+
+            parse_timestamp(&response.message).ok_or_else(|| {
+                crate::error::Error::SerializationFailed(format!(
+                    "Invalid modification time returned by server: {}.",
+                    response.message
+                ))
+            })
+        })
+    }
+
+    /// Get the checksum of a file, using the HASH command with the algorithm
+    /// currently selected on the server. The algorithms supported by the server
+    /// are advertised on [features](struct.Client.html#method.features).
+    pub async fn hash(&mut self, path: &str) -> Result<FileHash, crate::error::Error> {
+        retrying!(self, {
+            let response = self
+                .write_unary_command_expecting("HASH", path, vec![StatusCodeKind::FileStatus])
+                .await?;
+
+            FileHash::parse(&response.message)
+        })
+    }
+
+    /// Select the algorithm used by the HASH command, such as "MD5" or "SHA-256".
+    pub async fn set_hash_algorithm(&mut self, algorithm: &str) -> Result<(), crate::error::Error> {
+        self.write_command_expecting(
+            &format!("OPTS HASH {}", algorithm),
+            vec![StatusCodeKind::Ok],
+        )
+        .await?;
+        Ok(())
+    }
+
     /// Download a file at a path into a byte buffer.
     pub async fn retrieve_file(&mut self, path: &str) -> Result<Vec<u8>, crate::error::Error> {
-        let mut conn = self.get_data_connection().await?;
-        self.write_unary_command_expecting(
-            "RETR",
-            path,
+        retrying!(self, {
+            let mut buffer = Vec::with_capacity(1024);
+            self.retrieve_to(path, &mut buffer).await?;
+            Ok(buffer)
+        })
+    }
+
+    /// Download a file at a path into a writer, the data is copied in chunks
+    /// so the file is never fully loaded in memory. Returns the number of bytes downloaded.
+    pub async fn retrieve_to<W: AsyncWrite + Unpin>(
+        &mut self,
+        path: &str,
+        writer: W,
+    ) -> Result<u64, crate::error::Error> {
+        let total = if self.progress_callback.is_some() {
+            self.progress_total(path).await?
+        } else {
+            None
+        };
+        self.download(path, total, writer).await
+    }
+
+    /// Get the size of a file to report the download progress, if the server supports SIZE.
+    async fn progress_total(&mut self, path: &str) -> Result<Option<u64>, crate::error::Error> {
+        if !self.features().await?.size {
+            return Ok(None);
+        }
+
+        // A failure here is reported by the download itself.
+        Ok(self.size(path).await.ok())
+    }
+
+    /// Download a file into a writer, reporting the progress of
+    /// a file with a possibly known size.
+    async fn download<W: AsyncWrite + Unpin>(
+        &mut self,
+        path: &str,
+        total: Option<u64>,
+        mut writer: W,
+    ) -> Result<u64, crate::error::Error> {
+        let offset = self.restart_offset.unwrap_or(0);
+        let conn = self.start_transfer(&format!("RETR {}", path)).await?;
+        // Scope connection so it drops before reading server reply.
+        let (result, keepalives) = {
+            let conn = self.data_stream(conn, self.download_rate_limit);
+            let callback = self.progress_callback.as_mut();
+            let mut conn = ProgressStream::new(conn, callback, offset, total);
+            let transfer = async {
+                let downloaded = tokio::io::copy(&mut conn, &mut writer).await?;
+                writer.flush().await?;
+                Ok(downloaded)
+            };
+            Self::keep_alive(&mut self.stream, self.keepalive_interval, transfer).await
+        };
+
+        let downloaded = self.transfer_result(result, keepalives)?;
+        self.parse_transfer_reply(keepalives, vec![StatusCodeKind::RequestActionCompleted])
+            .await?;
+        Ok(downloaded)
+    }
+
+    /// Run a transfer while sending a NOOP on the control connection every
+    /// interval, if any. Returns the transfer result and the number of NOOP
+    /// commands sent, whose replies are read after the transfer.
+    async fn keep_alive<T, F>(
+        control: &mut BufReader<Stream>,
+        interval: Option<Duration>,
+        transfer: F,
+    ) -> (Result<T, crate::error::Error>, usize)
+    where
+        F: Future<Output = std::io::Result<T>>,
+    {
+        let interval = match interval {
+            Some(interval) => interval,
+            None => return (transfer.await.map_err(Into::into), 0),
+        };
+
+        let mut transfer = Box::pin(transfer);
+        let mut keepalives = 0;
+        loop {
+            match tokio::time::timeout(interval, &mut transfer).await {
+                Ok(result) => return (result.map_err(Into::into), keepalives),
+                Err(_) => match control.get_mut().write_all(b"NOOP\r\n").await {
+                    Ok(()) => keepalives += 1,
+                    Err(error) => return (Err(error.into()), keepalives),
+                },
+            }
+        }
+    }
+
+    /// Get the result of a transfer, when it failed the final reply and the replies
+    /// to the NOOP commands sent during it are left to be read before the next command.
+    fn transfer_result<T>(
+        &mut self,
+        result: Result<T, crate::error::Error>,
+        keepalives: usize,
+    ) -> Result<T, crate::error::Error> {
+        if result.is_err() {
+            self.pending_replies += keepalives + 1;
+        }
+        result
+    }
+
+    /// Read the final reply to a transfer along with the replies to the NOOP
+    /// commands sent during it, which may come before or after the final reply.
+    async fn parse_transfer_reply(
+        &mut self,
+        keepalives: usize,
+        valid_statuses: Vec<StatusCodeKind>,
+    ) -> Result<ServerResponse, crate::error::Error> {
+        let mut pending = keepalives;
+        let response = loop {
+            let response = self.parse_reply().await?;
+            if pending > 0 && response.status_code.kind == StatusCodeKind::Ok {
+                pending -= 1;
+            } else {
+                break response;
+            }
+        };
+        for _ in 0..pending {
+            self.parse_reply().await?;
+        }
+
+        Self::expect_status(response, valid_statuses)
+    }
+
+    /// Open a file at a path for reading, the returned handle reads
+    /// the file straight from the data connection.
+    pub async fn open_read(&mut self, path: &str) -> Result<DataReader<'_>, crate::error::Error> {
+        let conn = self.start_transfer(&format!("RETR {}", path)).await?;
+        let conn = self.data_stream(conn, self.download_rate_limit);
+        Ok(DataReader {
+            client: self,
+            conn: Some(conn),
+        })
+    }
+
+    /// Open a file at a path for writing, the returned handle writes
+    /// the file straight into the data connection.
+    pub async fn open_write(&mut self, path: &str) -> Result<DataWriter<'_>, crate::error::Error> {
+        let conn = self.start_transfer(&format!("STOR {}", path)).await?;
+        let conn = self.data_stream(conn, self.upload_rate_limit);
+        Ok(DataWriter {
+            client: self,
+            conn: Some(conn),
+        })
+    }
+
+    /// Open a data connection and issue a transfer command (such as RETR or STOR)
+    /// for it, the returned stream is ready once the server accepts the command.
+    ///
+    /// When the data channel is protected, the TLS handshake happens after the
+    /// server accepts the command, as many servers only start it at that point.
+    /// An offset set by [restart](struct.Client.html#method.restart) is sent
+    /// with REST once the data connection is set up.
+    pub async fn start_transfer(&mut self, cmd: &str) -> Result<Stream, crate::error::Error> {
+        // The restart offset only applies to this transfer.
+        let offset = self.restart_offset.take();
+        let conn = self.get_data_connection().await?;
+        if let Some(offset) = offset {
+            self.write_unary_command_expecting(
+                "REST",
+                &offset.to_string(),
+                vec![StatusCodeKind::RequestActionPending],
+            )
+            .await?;
+        }
+        self.write_command_expecting(
+            cmd,
             vec![
-                StatusCodeKind::TransferAboutToStart,
                 StatusCodeKind::TransferStarted,
+                StatusCodeKind::TransferAboutToStart,
             ],
         )
         .await?;
+        let conn = with_timeout(self.timeouts.connect, "Data connection", conn.establish()).await?;
 
-        let mut buffer = Vec::with_capacity(1024);
-        conn.read_to_end(&mut buffer).await?;
-        self.parse_reply_expecting(vec![StatusCodeKind::RequestActionCompleted])
-            .await?;
-        Ok(buffer)
+        self.secure_data_connection(conn).await
+    }
+
+    /// Apply the data inactivity timeout and a bandwidth limit to a data connection.
+    fn data_stream(&self, conn: Stream, rate_limit: Option<u64>) -> DataStream {
+        ThrottledStream::new(TimeoutStream::new(conn, self.timeouts.data), rate_limit)
+    }
+
+    /// Wrap a data connection in TLS if the data channel is protected.
+    pub async fn secure_data_connection(
+        &self,
+        conn: TcpStream,
+    ) -> Result<Stream, crate::error::Error> {
+        match &self.tls_connector {
+            Some(connector) if self.data_protection => {
+                // The connector shares its session cache with the control
+                // connection, so the data connection resumes its TLS session.
+                let handshake = connector.connect(dns_name(&self.hostname)?, conn);
+                let secure_conn =
+                    with_timeout(self.timeouts.connect, "TLS handshake", handshake).await?;
+                Ok(Stream::Secure(Box::new(secure_conn)))
+            }
+            _ => Ok(Stream::Plain(conn)),
+        }
     }
 
     /// Acquire the data connection using the current ClientMode.
-    pub async fn get_data_connection(&mut self) -> Result<TcpStream, crate::error::Error> {
+    pub async fn get_data_connection(&mut self) -> Result<DataConnection, crate::error::Error> {
         match self.mode {
-            ClientMode::Active => unimplemented!(),
-            ClientMode::Passive => self.passive_mode_connection().await,
-            ClientMode::ExtendedPassive => self.extended_passive_mode_connection().await,
+            ClientMode::Active => {
+                let server = self.stream.get_ref().get_ref().peer_addr()?.ip();
+                let listener = self.active_mode_connection().await?;
+                Ok(DataConnection::Listening(listener, server))
+            }
+            ClientMode::Passive => Ok(DataConnection::Connected(
+                self.passive_mode_connection().await?,
+            )),
+            ClientMode::ExtendedPassive => Ok(DataConnection::Connected(
+                self.extended_passive_mode_connection().await?,
+            )),
+        }
+    }
+
+    /// Create an active mode listener and announce it to the server, using
+    /// PORT for IPV4 addresses and EPRT for IPV6 addresses.
+    pub async fn active_mode_connection(&mut self) -> Result<TcpListener, crate::error::Error> {
+        let control_address = self.stream.get_ref().get_ref().local_addr()?.ip();
+        let address = self.active_address.unwrap_or(control_address);
+        let listener = self.bind_active_listener(address).await?;
+
+        // The server can not connect to an unspecified address, so we announce
+        // the address used by the control connection instead.
+        let port = listener.local_addr()?.port();
+        let announced = if address.is_unspecified() {
+            control_address
+        } else {
+            address
+        };
+        match announced {
+            IpAddr::V4(ip) => {
+                let [h1, h2, h3, h4] = ip.octets();
+                let argument = format!("{},{},{},{},{},{}", h1, h2, h3, h4, port / 256, port % 256);
+                self.write_unary_command_expecting("PORT", &argument, vec![StatusCodeKind::Ok])
+                    .await?;
+            }
+            IpAddr::V6(ip) => {
+                let argument = format!("|2|{}|{}|", ip, port);
+                self.write_unary_command_expecting("EPRT", &argument, vec![StatusCodeKind::Ok])
+                    .await?;
+            }
+        }
+
+        Ok(listener)
+    }
+
+    async fn bind_active_listener(
+        &self,
+        address: IpAddr,
+    ) -> Result<TcpListener, crate::error::Error> {
+        let ports = match &self.active_ports {
+            Some(ports) => ports.clone(),
+            None => return Ok(TcpListener::bind(SocketAddr::new(address, 0)).await?),
+        };
+
+        for port in ports {
+            match TcpListener::bind(SocketAddr::new(address, port)).await {
+                Ok(listener) => return Ok(listener),
+                Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+                Err(e) => return Err(e.into()),
+            }
         }
+
+        Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            "No free port available on the active mode port range.",
+        )
+        .into())
     }
 
     /// Create a extended passive mode connection.
@@ -512,7 +1932,12 @@ impl Client {
             .await?;
         let socket = self.decode_extended_passive_mode_socket(&response.message)?;
 
-        Ok(TcpStream::connect(socket).await?)
+        with_timeout(
+            self.timeouts.connect,
+            "Data connection",
+            TcpStream::connect(socket),
+        )
+        .await
     }
 
     /// Create a passive mode connection.
@@ -522,7 +1947,12 @@ impl Client {
             .await?;
         let socket = self.decode_passive_mode_ip(&response.message)?;
 
-        Ok(TcpStream::connect(socket).await?)
+        with_timeout(
+            self.timeouts.connect,
+            "Data connection",
+            TcpStream::connect(socket),
+        )
+        .await
     }
 
     /// Write a command with one argument to the server expecting a list of positive status codes.
@@ -542,10 +1972,8 @@ impl Client {
         cmd: &str,
         arg: &str,
     ) -> Result<(), crate::error::Error> {
-        let text = format!("{} {}\r\n", cmd, arg);
-        self.stream.get_mut().write_all(text.as_bytes()).await?;
-
-        Ok(())
+        self.reconnect_if_disconnected().await?;
+        self.send_command(&format!("{} {}", cmd, arg)).await
     }
 
     /// Write a command to the server expecting a list of positive status codes.
@@ -560,8 +1988,49 @@ impl Client {
 
     /// Write a command to the server.
     pub async fn write_command(&mut self, cmd: &str) -> Result<(), crate::error::Error> {
-        let text = format!("{}\r\n", cmd);
-        self.stream.get_mut().write_all(text.as_bytes()).await?;
+        self.reconnect_if_disconnected().await?;
+        self.send_command(cmd).await
+    }
+
+    /// Reconnect before writing a command if the connection was lost
+    /// and a reconnect policy is set on the client.
+    async fn reconnect_if_disconnected(&mut self) -> Result<(), crate::error::Error> {
+        if self.disconnected && self.reconnect_policy.is_some() {
+            let mut attempts = 0;
+            self.reconnect_with_policy(&mut attempts).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a command line to the server, without reconnecting first.
+    async fn send_command(&mut self, line: &str) -> Result<(), crate::error::Error> {
+        self.read_pending_replies().await?;
+        let text = format!("{}\r\n", line);
+        match self.stream.get_mut().write_all(text.as_bytes()).await {
+            Ok(()) => Ok(()),
+            Err(error) => Err(self.control_connection_error(error)),
+        }
+    }
+
+    /// Write a command line to the server without reconnecting first,
+    /// expecting a list of positive status codes.
+    async fn send_command_expecting(
+        &mut self,
+        line: &str,
+        valid_statuses: Vec<StatusCodeKind>,
+    ) -> Result<ServerResponse, crate::error::Error> {
+        self.send_command(line).await?;
+        self.parse_reply_expecting(valid_statuses).await
+    }
+
+    /// Read the replies left by a transfer that failed or whose handle was dropped
+    /// without being finished, the transfer may have been interrupted so any status is accepted.
+    async fn read_pending_replies(&mut self) -> Result<(), crate::error::Error> {
+        while self.pending_replies > 0 {
+            self.pending_replies -= 1;
+            self.parse_reply().await?;
+        }
 
         Ok(())
     }
@@ -572,7 +2041,14 @@ impl Client {
         valid_statuses: Vec<StatusCodeKind>,
     ) -> Result<ServerResponse, crate::error::Error> {
         let response = self.parse_reply().await?;
+        Self::expect_status(response, valid_statuses)
+    }
 
+    /// Check that a reply has one of a list of status codes, or any positive status.
+    fn expect_status(
+        response: ServerResponse,
+        valid_statuses: Vec<StatusCodeKind>,
+    ) -> Result<ServerResponse, crate::error::Error> {
         let is_expected_status = valid_statuses.contains(&response.status_code.kind);
         // We are a bit liberal on what we accept.
         let is_positive_status = response.status_code.is_valid();
@@ -584,26 +2060,85 @@ impl Client {
         if is_expected_status || is_positive_status {
             Ok(response)
         } else {
-            Err(crate::error::Error::UnexpectedStatusCode(
+            Err(crate::error::Error::UnexpectedReply(UnexpectedReply::new(
+                response.status_code.code,
                 response.summarize_error(valid_statuses),
-            ))
+            )))
         }
     }
 
     /// Parse the server reply into a ServerResponse.
     pub async fn parse_reply(&mut self) -> Result<ServerResponse, crate::error::Error> {
-        self.buffer.clear();
-        self.stream.read_line(&mut self.buffer).await?;
-        Ok(ServerResponse::parse(&self.buffer))
+        self.read_reply_lines().await?;
+        let response = ServerResponse::parse(&self.buffer);
+        // The server closes the control connection after a 421 reply.
+        if response.status_code.kind == StatusCodeKind::ServiceNotAvailable {
+            self.disconnected = true;
+        }
+
+        Ok(response)
     }
 
-    /// Read the server reply as a raw string.
+    /// Read the server reply as a raw string, multi-line replies
+    /// are returned whole, including the line breaks.
     pub async fn read_reply(&mut self) -> Result<String, crate::error::Error> {
-        self.buffer.clear();
-        self.stream.read_line(&mut self.buffer).await?;
+        self.read_reply_lines().await?;
         Ok(self.buffer.clone())
     }
 
+    /// Read a whole reply into the buffer. A multi-line reply starts with
+    /// "CODE-" and goes on until a line starting with "CODE " is found,
+    /// as described on RFC959.
+    async fn read_reply_lines(&mut self) -> Result<(), crate::error::Error> {
+        let timeout = self.timeouts.reply;
+        with_timeout(timeout, "Server reply", self.read_reply_text()).await
+    }
+
+    async fn read_reply_text(&mut self) -> Result<(), crate::error::Error> {
+        self.buffer.clear();
+        self.read_reply_line().await?;
+        if self.buffer.get(3..4) != Some("-") {
+            return Ok(());
+        }
+
+        let code = self.buffer[0..3].to_string();
+        loop {
+            let line_start = self.buffer.len();
+            self.read_reply_line().await?;
+            let line = self.buffer[line_start..].trim_end();
+            if line.get(0..3) == Some(code.as_str()) && matches!(line.get(3..4), Some(" ") | None) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Read a single line of a reply, appending it to the buffer.
+    async fn read_reply_line(&mut self) -> Result<(), crate::error::Error> {
+        let read = match self.stream.read_line(&mut self.buffer).await {
+            Ok(read) => read,
+            Err(error) => return Err(self.control_connection_error(error)),
+        };
+        if read == 0 {
+            self.disconnected = true;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "The server closed the control connection.",
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Mark the client as disconnected if an error on the control
+    /// connection means the connection was lost.
+    fn control_connection_error(&mut self, error: std::io::Error) -> crate::error::Error {
+        if is_connection_lost(&error) {
+            self.disconnected = true;
+        }
+        error.into()
+    }
+
     fn decode_passive_mode_ip(
         &self,
         message: &str,
@@ -661,6 +2196,7 @@ impl Client {
                 let ip = self
                     .stream
                     .get_ref()
+                    .get_ref()
                     .peer_addr()
                     .map_err(move |_| cant_parse_error())?
                     .ip();
@@ -670,3 +2206,41 @@ impl Client {
         }
     }
 }
+
+/// Get an IPV4 address mapped to IPV6 as the IPV4 address,
+/// as dual stack listeners report IPV4 peers.
+fn canonical_ip(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v6),
+        },
+        v4 => v4,
+    }
+}
+
+/// Returns whether an IO error means the connection was lost.
+fn is_connection_lost(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::NotConnected
+    )
+}
+
+/// Get the name used to verify the certificate of a server.
+///
+/// Certificates can only be verified against DNS names, so servers
+/// reached by IP address can not be connected to with TLS.
+fn dns_name(hostname: &str) -> Result<DNSNameRef<'_>, crate::error::Error> {
+    DNSNameRef::try_from_ascii_str(hostname).map_err(|_| {
+        crate::error::Error::TlsError(format!(
+            "{} is not a valid DNS name to verify the server certificate, \
+             IP addresses are not supported.",
+            hostname
+        ))
+    })
+}
@@ -2,26 +2,68 @@
 //! ftp-rs crate.
 
 use derive_error::Error;
-use native_tls::{Error as TlsError, HandshakeError};
-use std::net::TcpStream;
 
 /// A generic client error, basically anything that can go wrong with
 /// a request has a variant on this enum.
 #[derive(Debug, Error)]
 pub enum Error {
     /// IO Error
+    #[error(no_from)]
     IoError(std::io::Error),
     /// Unexpected status code
     #[error(msg_embedded, no_from, non_std)]
     UnexpectedStatusCode(String),
+    /// Unexpected status code on a server reply
+    #[error(msg_embedded, no_from, non_std)]
+    UnexpectedReply(UnexpectedReply),
     /// A (de)serialization failed
     #[error(msg_embedded, no_from, non_std)]
     SerializationFailed(String),
     /// Invalid socket IP from passive mode
     #[error(msg_embedded, no_from, non_std)]
     InvalidSocketPassiveMode(String),
-    /// Error on TLS handshake
-    TlsHandshakeError(HandshakeError<TcpStream>),
-    /// Error while creating TLS connector
-    TlsError(TlsError),
+    /// Invalid offset to restart a transfer
+    #[error(msg_embedded, no_from, non_std)]
+    InvalidRestartOffset(String),
+    /// A connection, reply or data transfer took longer than allowed
+    #[error(msg_embedded, no_from, non_std)]
+    Timeout(String),
+    /// Invalid TLS configuration, such as a hostname that is not a
+    /// valid DNS name or no trusted root certificates. Errors on the
+    /// TLS handshake itself are IO errors.
+    #[error(msg_embedded, no_from, non_std)]
+    TlsError(String),
+}
+
+/// A server reply with a status code that was not expected, keeping
+/// the status code to tell apart the errors reported by the server.
+#[derive(Debug)]
+pub struct UnexpectedReply {
+    /// The status code of the reply.
+    pub code: u16,
+    message: String,
+}
+
+impl UnexpectedReply {
+    pub(crate) fn new(code: u16, message: String) -> Self {
+        UnexpectedReply { code, message }
+    }
+
+    /// Get a description of the reply and the expected status codes,
+    /// which is how the error is displayed.
+    pub fn as_str(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        // Timed out operations, such as data connections
+        // without activity, are reported as timeouts.
+        if err.kind() == std::io::ErrorKind::TimedOut {
+            Error::Timeout(err.to_string())
+        } else {
+            Error::IoError(err)
+        }
+    }
 }
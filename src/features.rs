@@ -0,0 +1,111 @@
+//! Contains code for parsing the server capabilities
+//! advertised through the FEAT command, described on RFC2389.
+use crate::client::ServerResponse;
+
+/// The set of capabilities advertised by a server on
+/// the reply to the FEAT command.
+///
+/// Features that are not known by this crate are still
+/// listed on [raw](struct.Features.html#structfield.raw).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Features {
+    /// The facts supported by MLST and MLSD, in lowercase.
+    /// None if the server does not support MLST.
+    pub mlst_facts: Option<Vec<String>>,
+    /// Support for UTF-8 encoded paths.
+    pub utf8: bool,
+    /// Support for the SIZE command.
+    pub size: bool,
+    /// Support for the MDTM command.
+    pub mdtm: bool,
+    /// Support for the MFMT command.
+    pub mfmt: bool,
+    /// Support for restarting stream mode transfers with REST.
+    pub rest_stream: bool,
+    /// Support for the EPSV command.
+    pub epsv: bool,
+    /// Support for the EPRT command.
+    pub eprt: bool,
+    /// The security mechanisms accepted by AUTH, such as TLS.
+    pub auth: Vec<String>,
+    /// Support for the PBSZ command.
+    pub pbsz: bool,
+    /// Support for the PROT command.
+    pub prot: bool,
+    /// The algorithms supported by the HASH command.
+    /// None if the server does not support HASH.
+    pub hash_algorithms: Option<Vec<String>>,
+    /// Support for the TVFS path conventions.
+    pub tvfs: bool,
+    /// Every feature line sent by the server.
+    pub raw: Vec<String>,
+}
+
+impl Features {
+    /// Parse the capabilities from the reply to a FEAT command.
+    pub fn parse(response: &ServerResponse) -> Self {
+        let mut features = Features::default();
+        if !response.is_multiline() {
+            return features;
+        }
+
+        // The first and last lines are not features, only the header and footer.
+        let lines = response.lines();
+        for line in &lines[1..lines.len() - 1] {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            features.raw.push(line.to_string());
+
+            let (name, parameters) = match line.find(' ') {
+                Some(index) => (&line[..index], line[index + 1..].trim()),
+                None => (line, ""),
+            };
+            match name.to_uppercase().as_str() {
+                "MLST" => features.mlst_facts = Some(Self::parse_list(parameters)),
+                "UTF8" => features.utf8 = true,
+                "SIZE" => features.size = true,
+                "MDTM" => features.mdtm = true,
+                "MFMT" => features.mfmt = true,
+                "REST" => features.rest_stream = parameters.eq_ignore_ascii_case("STREAM"),
+                "EPSV" => features.epsv = true,
+                "EPRT" => features.eprt = true,
+                "AUTH" => features.auth.extend(
+                    parameters
+                        .split(';')
+                        .filter(|mechanism| !mechanism.is_empty())
+                        .map(|mechanism| mechanism.trim().to_uppercase()),
+                ),
+                "PBSZ" => features.pbsz = true,
+                "PROT" => features.prot = true,
+                "HASH" => features.hash_algorithms = Some(Self::parse_list(parameters)),
+                "TVFS" => features.tvfs = true,
+                _ => {}
+            }
+        }
+
+        features
+    }
+
+    /// Returns whether the server advertised a feature by name, such as "MLST"
+    /// or "REST STREAM". This is useful for features not mapped by this struct.
+    pub fn supports(&self, feature: &str) -> bool {
+        self.raw.iter().any(|line| {
+            let name_matches = line
+                .get(..feature.len())
+                .is_some_and(|name| name.eq_ignore_ascii_case(feature));
+            name_matches && matches!(line[feature.len()..].chars().next(), None | Some(' '))
+        })
+    }
+
+    /// Parse a list such as "type*;size*;modify;", removing the '*'
+    /// markers used for the values currently enabled.
+    fn parse_list(parameters: &str) -> Vec<String> {
+        parameters
+            .split(';')
+            .map(|value| value.trim().trim_end_matches('*').to_lowercase())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+}
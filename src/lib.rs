@@ -23,10 +23,23 @@
 //!
 #![deny(missing_docs)]
 
+pub mod checksum;
 pub mod client;
 pub mod error;
+pub mod features;
+pub mod listing;
+pub mod pool;
+pub mod progress;
 pub mod status_code;
+pub mod stream;
 pub mod sync;
+pub mod throttle;
+pub mod timeout;
+pub mod tree;
+
+/// The TLS library used by secure connections, to
+/// build configurations trusting custom certificates.
+pub use tokio_rustls::rustls;
 
 /// The prelude module contains some useful default imports.
 pub mod prelude {
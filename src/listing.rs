@@ -0,0 +1,459 @@
+//! Contains the structured entries of directory listings and
+//! the code to parse them from the machine-readable format of
+//! MLSD and MLST, described on RFC3659, and from the free-form
+//! LIST output of Unix and Windows/IIS servers.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The type of an entry on a directory listing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// The listed directory itself, sent by some servers on MLSD.
+    CurrentDirectory,
+    /// The parent of the listed directory, sent by some servers on MLSD.
+    ParentDirectory,
+    /// A symbolic link.
+    Link,
+    /// Any other type, with the type as sent by the server.
+    Other(String),
+}
+
+/// A file, directory or any other entry on a directory listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The name of the entry, or the full path when listed with MLST.
+    pub name: String,
+    /// The type of the entry.
+    pub kind: EntryKind,
+    /// The size of the entry in bytes.
+    pub size: Option<u64>,
+    /// The last modification time of the entry.
+    pub modified: Option<SystemTime>,
+    /// The permissions of the current user on the entry, using
+    /// the letters of the MLST perm fact (e.g. "adfrw").
+    pub permissions: Option<String>,
+    /// An identifier that is unique for the entry on the server.
+    pub unique: Option<String>,
+    /// The unix file mode, such as 0o644.
+    pub unix_mode: Option<u32>,
+    /// The target of a symbolic link, when known.
+    pub link_target: Option<String>,
+}
+
+impl Entry {
+    /// Create an entry with a name and a kind, with every other
+    /// information missing.
+    pub fn new(name: &str, kind: EntryKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            size: None,
+            modified: None,
+            permissions: None,
+            unique: None,
+            unix_mode: None,
+            link_target: None,
+        }
+    }
+
+    /// Returns whether the entry is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+
+    /// Returns whether the entry is a directory, not counting
+    /// the current and parent directory entries.
+    pub fn is_directory(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+
+    /// Parse an entry from a line in the MLSD/MLST format, such as
+    /// "type=file;size=1024;modify=20200101120000; file.txt".
+    pub fn parse_mlsx(line: &str) -> Result<Self, crate::error::Error> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let cant_parse_error = || {
+            crate::error::Error::SerializationFailed(format!(
+                "Cannot parse machine listing entry: {}.",
+                line
+            ))
+        };
+        // The facts are separated from the name by a single space,
+        // the name itself may contain spaces.
+        let separator = line.find(' ').ok_or_else(cant_parse_error)?;
+        let (facts, name) = (&line[..separator], &line[separator + 1..]);
+        if name.is_empty() {
+            return Err(cant_parse_error());
+        }
+
+        let mut entry = Entry::new(name, EntryKind::Other(String::new()));
+        for fact in facts.split(';').filter(|fact| !fact.is_empty()) {
+            let (key, value) = match fact.find('=') {
+                Some(index) => (fact[..index].to_lowercase(), &fact[index + 1..]),
+                None => return Err(cant_parse_error()),
+            };
+            match key.as_str() {
+                "type" => entry.kind = Self::parse_mlsx_type(value, &mut entry.link_target),
+                "size" => entry.size = Some(value.parse().map_err(|_| cant_parse_error())?),
+                "modify" => {
+                    entry.modified = Some(parse_timestamp(value).ok_or_else(cant_parse_error)?)
+                }
+                "perm" => entry.permissions = Some(value.to_string()),
+                "unique" => entry.unique = Some(value.to_string()),
+                "unix.mode" => {
+                    entry.unix_mode =
+                        Some(u32::from_str_radix(value, 8).map_err(|_| cant_parse_error())?)
+                }
+                _ => {}
+            }
+        }
+
+        Ok(entry)
+    }
+
+    fn parse_mlsx_type(value: &str, link_target: &mut Option<String>) -> EntryKind {
+        let lowercase = value.to_lowercase();
+        match lowercase.as_str() {
+            "file" => EntryKind::File,
+            "dir" => EntryKind::Directory,
+            "cdir" => EntryKind::CurrentDirectory,
+            "pdir" => EntryKind::ParentDirectory,
+            "os.unix=symlink" | "os.unix=slink" => EntryKind::Link,
+            _ if lowercase.starts_with("os.unix=slink:") => {
+                *link_target = Some(value["os.unix=slink:".len()..].to_string());
+                EntryKind::Link
+            }
+            _ => EntryKind::Other(value.to_string()),
+        }
+    }
+
+    /// Parse an entry from a line of LIST output, on the Unix or DOS format.
+    ///
+    /// The dates sent on LIST have no timezone, so they are assumed to
+    /// be in UTC. Unix dates without a year are assumed to be in the last year.
+    pub fn parse_list(line: &str) -> Result<Self, crate::error::Error> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        Self::parse_unix_list(line)
+            .or_else(|| Self::parse_dos_list(line))
+            .ok_or_else(|| {
+                crate::error::Error::SerializationFailed(format!(
+                    "Cannot parse directory listing entry: {}.",
+                    line
+                ))
+            })
+    }
+
+    /// Parse a line such as "-rw-r--r-- 1 owner group 1024 Jan 01 12:00 file.txt".
+    fn parse_unix_list(line: &str) -> Option<Self> {
+        let tokens = tokenize(line);
+        let permissions = tokens.first()?.1;
+        let mut chars = permissions.chars();
+        let kind = match chars.next()? {
+            '-' => EntryKind::File,
+            'd' => EntryKind::Directory,
+            'l' => EntryKind::Link,
+            'b' => EntryKind::Other("block device".to_string()),
+            'c' => EntryKind::Other("character device".to_string()),
+            'p' => EntryKind::Other("pipe".to_string()),
+            's' => EntryKind::Other("socket".to_string()),
+            _ => return None,
+        };
+        let unix_mode = parse_unix_mode(permissions.get(1..10)?)?;
+
+        // The owner and group columns are optional, so we look for the date to
+        // find the size and the name. The name is what follows the date.
+        let month_index = (3..tokens.len()).find(|&index| {
+            parse_month(tokens[index].1).is_some()
+                && tokens.get(index + 2).is_some()
+                && tokens[index - 1]
+                    .1
+                    .bytes()
+                    .all(|byte| byte.is_ascii_digit())
+        })?;
+        let (_, month) = tokens[month_index];
+        let (_, day) = tokens[month_index + 1];
+        let (time_start, time) = tokens[month_index + 2];
+        // The name is separated from the date by a single space and may
+        // contain any spaces itself, so we keep the rest of the line.
+        let name = line.get(time_start + time.len() + 1..)?;
+        if name.trim().is_empty() {
+            return None;
+        }
+
+        // Device entries have "major, minor" in place of the size.
+        let is_device = tokens[month_index - 2].1.ends_with(',');
+        let size = if is_device {
+            None
+        } else {
+            Some(tokens[month_index - 1].1.parse().ok()?)
+        };
+        let modified = parse_unix_date(month, day, time)?;
+
+        let (name, link_target) = match (&kind, name.find(" -> ")) {
+            (EntryKind::Link, Some(index)) => (&name[..index], Some(name[index + 4..].to_string())),
+            _ => (name, None),
+        };
+
+        let mut entry = Entry::new(name, kind);
+        entry.size = size;
+        entry.modified = Some(modified);
+        entry.unix_mode = Some(unix_mode);
+        entry.link_target = link_target;
+        Some(entry)
+    }
+
+    /// Parse a line such as "01-16-20  02:30PM       <DIR>          some dir".
+    fn parse_dos_list(line: &str) -> Option<Self> {
+        let tokens = tokenize(line);
+        let (_, date) = *tokens.first()?;
+        let (_, time) = *tokens.get(1)?;
+        let (_, size) = *tokens.get(2)?;
+        let (name_start, _) = *tokens.get(3)?;
+
+        let date: Vec<u64> = date
+            .split('-')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        let (month, day, year) = match date[..] {
+            [month, day, year] if year < 70 => (month, day, year + 2000),
+            [month, day, year] if year < 100 => (month, day, year + 1900),
+            [month, day, year] => (month, day, year),
+            _ => return None,
+        };
+
+        let time = time.to_uppercase();
+        let (time, is_pm) = match (time.strip_suffix("AM"), time.strip_suffix("PM")) {
+            (Some(time), _) => (time.to_string(), false),
+            (_, Some(time)) => (time.to_string(), true),
+            _ => (time, false),
+        };
+        let (hour, minute) = parse_clock(&time)?;
+        let hour = match (hour, is_pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (hour, true) => hour + 12,
+            (hour, false) => hour,
+        };
+
+        let (kind, size) = if size.eq_ignore_ascii_case("<DIR>") {
+            (EntryKind::Directory, None)
+        } else {
+            (EntryKind::File, Some(size.parse().ok()?))
+        };
+
+        let mut entry = Entry::new(&line[name_start..], kind);
+        entry.size = size;
+        entry.modified = Some(datetime(year, month, day, hour, minute, 0)?);
+        Some(entry)
+    }
+}
+
+/// The entries parsed from the output of a LIST command, along with
+/// the lines that could not be parsed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Listing {
+    /// The entries that were parsed successfully.
+    pub entries: Vec<Entry>,
+    /// The lines that are not in any known format.
+    pub unparsed: Vec<String>,
+}
+
+impl Listing {
+    /// Parse the output of a LIST command, lines on the Unix "ls -l" format
+    /// and on the DOS format used by Windows/IIS servers are supported.
+    ///
+    /// The "total" line sent by some Unix servers and empty lines are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut listing = Listing::default();
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with("total ") {
+                continue;
+            }
+            match Entry::parse_list(line) {
+                Ok(entry) => listing.entries.push(entry),
+                Err(_) => listing.unparsed.push(line.to_string()),
+            }
+        }
+
+        listing
+    }
+}
+
+/// Split a line on whitespace, keeping the byte offset where each token starts.
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, character) in line.char_indices() {
+        match (character.is_whitespace(), start) {
+            (true, Some(token_start)) => {
+                tokens.push((token_start, &line[token_start..index]));
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    if let Some(token_start) = start {
+        tokens.push((token_start, &line[token_start..]));
+    }
+
+    tokens
+}
+
+/// Parse a mode such as "rwxr-sr-T" into its numeric form.
+fn parse_unix_mode(text: &str) -> Option<u32> {
+    let mut mode = 0;
+    for (index, character) in text.chars().enumerate() {
+        // The bit set by the character, if any, and the special bit for the class.
+        let bit = 1 << (8 - index);
+        let special = 1 << (11 - index / 3);
+        mode |= match (index % 3, character) {
+            (_, '-') => 0,
+            (0, 'r') | (1, 'w') | (2, 'x') => bit,
+            (2, 's') | (2, 't') => bit | special,
+            (2, 'S') | (2, 'T') => special,
+            _ => return None,
+        };
+    }
+
+    Some(mode)
+}
+
+fn parse_month(text: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let text = text.to_lowercase();
+    MONTHS
+        .iter()
+        .position(|month| *month == text)
+        .map(|index| index as u64 + 1)
+}
+
+fn parse_clock(text: &str) -> Option<(u64, u64)> {
+    let index = text.find(':')?;
+    let hour = text[..index].parse().ok()?;
+    let minute = text[index + 1..].parse().ok()?;
+    Some((hour, minute))
+}
+
+/// Parse a Unix listing date, such as "Jan 01 12:00" or "Jan 01 2020".
+fn parse_unix_date(month: &str, day: &str, time_or_year: &str) -> Option<SystemTime> {
+    let month = parse_month(month)?;
+    let day = day.parse().ok()?;
+    if time_or_year.contains(':') {
+        let (hour, minute) = parse_clock(time_or_year)?;
+        // Dates without a year are within the last year, so a date after
+        // today (with some tolerance for clock differences) is from last year.
+        let now = SystemTime::now();
+        let year = civil_from_days(now.duration_since(UNIX_EPOCH).ok()?.as_secs() / 86400).0;
+        let date = datetime(year, month, day, hour, minute, 0)?;
+        if date > now + Duration::from_secs(86400) {
+            datetime(year - 1, month, day, hour, minute, 0)
+        } else {
+            Some(date)
+        }
+    } else {
+        datetime(time_or_year.parse().ok()?, month, day, 0, 0, 0)
+    }
+}
+
+/// Create a UTC time from its parts, returning None for invalid parts.
+fn datetime(
+    year: u64,
+    month: u64,
+    day: u64,
+    hour: u64,
+    minute: u64,
+    second: u64,
+) -> Option<SystemTime> {
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Parse a timestamp on the YYYYMMDDHHMMSS[.sss] format used by
+/// MDTM and MLST, the time is always in UTC.
+pub fn parse_timestamp(text: &str) -> Option<SystemTime> {
+    let text = text.trim();
+    let (date, fraction) = match text.find('.') {
+        Some(index) => (&text[..index], Some(&text[index + 1..])),
+        None => (text, None),
+    };
+    if date.len() != 14 || !date.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let number = |range: std::ops::Range<usize>| date[range].parse::<u64>().ok();
+    let (year, month, day) = (number(0..4)?, number(4..6)?, number(6..8)?);
+    let (hour, minute, second) = (number(8..10)?, number(10..12)?, number(12..14)?);
+    let mut time = datetime(year, month, day, hour, minute, second)?;
+    if let Some(fraction) = fraction {
+        if fraction.is_empty() || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        // Only keep up to nanoseconds precision.
+        let digits = &fraction[..fraction.len().min(9)];
+        let nanos: u64 = digits.parse().ok()?;
+        time += Duration::from_nanos(nanos * 10u64.pow(9 - digits.len() as u32));
+    }
+
+    Some(time)
+}
+
+/// Get the number of days of a month, accounting for leap years.
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Count the days since 1970-01-01 of a date on the gregorian calendar,
+/// using the algorithm described by Howard Hinnant.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Get the year, month and day of a count of days since 1970-01-01,
+/// the inverse of days_from_civil.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
@@ -0,0 +1,259 @@
+//! Contains a pool of logged in clients, to share FTP sessions
+//! between concurrent tasks without connecting for each one.
+use crate::client::Client;
+use crate::status_code::StatusCodeKind;
+use crate::timeout::TimeoutStream;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::Poll;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// The future returned by the function used by a pool to connect new clients.
+pub type ConnectFuture = Pin<Box<dyn Future<Output = Result<Client, crate::error::Error>> + Send>>;
+
+/// A pool of logged in clients with a maximum size. Idle clients are checked
+/// with a NOOP when handed out and broken clients are discarded, a new client
+/// is connected when no idle client is available.
+///
+/// The pool can be shared between tasks with an Arc:
+/// ```rust,no_run
+/// use ftp_client::{client::Client, pool::Pool};
+///
+/// let pool = Pool::new(4, || Client::connect("localhost", "user", "user"));
+/// ```
+pub struct Pool {
+    connect: Box<dyn Fn() -> ConnectFuture + Send + Sync>,
+    idle: Mutex<Vec<Client>>,
+    permits: Semaphore,
+    max_size: usize,
+}
+
+impl Pool {
+    /// Create a pool of at most max_size clients, new clients
+    /// are connected and logged in with the provided function.
+    pub fn new<F, Fut>(max_size: usize, connect: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Client, crate::error::Error>> + Send + 'static,
+    {
+        Pool {
+            connect: Box::new(move || Box::pin(connect())),
+            idle: Mutex::new(Vec::new()),
+            permits: Semaphore::new(max_size),
+            max_size,
+        }
+    }
+
+    /// Get a client from the pool, waiting until one is available if
+    /// max_size clients are already in use. The client goes back to
+    /// the pool when dropped.
+    pub async fn get(&self) -> Result<PooledClient<'_>, crate::error::Error> {
+        let permit = self.permits.acquire().await;
+
+        while let Some(mut client) = self.take_idle() {
+            if client.noop().await.is_ok() {
+                return Ok(PooledClient {
+                    client: Some(client),
+                    pool: self,
+                    _permit: permit,
+                });
+            }
+        }
+
+        let client = (self.connect)().await?;
+        Ok(PooledClient {
+            client: Some(client),
+            pool: self,
+            _permit: permit,
+        })
+    }
+
+    /// Download a file into a local file in segments, fetched in parallel by
+    /// clients from the pool. The file size is requested with SIZE and each
+    /// segment is downloaded in binary mode from its offset, using REST, with
+    /// the data connection closed as soon as the segment is complete.
+    /// Returns the number of bytes downloaded.
+    ///
+    /// At most max_size segments are downloaded at once, the server must
+    /// support SIZE and REST. Clients failing a segment are discarded.
+    pub async fn download_segmented<P: AsRef<Path>>(
+        &self,
+        path: &str,
+        local_path: P,
+        segments: u64,
+    ) -> Result<u64, crate::error::Error> {
+        let local_path = local_path.as_ref();
+        let size = {
+            let mut client = self.get().await?;
+            client.binary().await?;
+            client.size(path).await?
+        };
+        let mut file = tokio::fs::File::create(local_path).await?;
+        file.set_len(size).await?;
+
+        let segments = segments.max(1);
+        let segment_size = (size + segments - 1) / segments;
+        let downloads = (0..size)
+            .step_by(segment_size.max(1) as usize)
+            .map(|start| {
+                let end = (start + segment_size).min(size);
+                self.download_segment(path, local_path, start, end, end == size)
+            })
+            .collect();
+
+        let mut downloaded = 0;
+        for result in join_all(downloads).await {
+            downloaded += result?;
+        }
+        Ok(downloaded)
+    }
+
+    /// Download the bytes from start to end of a file into the same range of a local file.
+    async fn download_segment(
+        &self,
+        path: &str,
+        local_path: &Path,
+        start: u64,
+        end: u64,
+        last: bool,
+    ) -> Result<u64, crate::error::Error> {
+        let mut client = self.get().await?;
+        let result = Self::fetch_range(&mut client, path, local_path, start, end, last).await;
+        if result.is_err() {
+            // The control connection may be left waiting for a reply.
+            client.discard();
+        }
+        result
+    }
+
+    async fn fetch_range(
+        client: &mut Client,
+        path: &str,
+        local_path: &Path,
+        start: u64,
+        end: u64,
+        last: bool,
+    ) -> Result<u64, crate::error::Error> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(local_path)
+            .await?;
+        file.seek(SeekFrom::Start(start)).await?;
+
+        client.binary().await?;
+        client.restart(start).await?;
+        let conn = client.start_transfer(&format!("RETR {}", path)).await?;
+        // Scope connection so it drops before reading server reply.
+        let downloaded = {
+            let conn = TimeoutStream::new(conn, client.timeouts().data);
+            let mut segment = conn.take(end - start);
+            tokio::io::copy(&mut segment, &mut file).await?
+        };
+        file.flush().await?;
+        if downloaded < end - start {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("Segment of {} ended at byte {}.", path, start + downloaded),
+            )
+            .into());
+        }
+
+        // Closing the data connection early is reported by most servers
+        // with an error reply, only the last segment must be complete.
+        if last {
+            client
+                .parse_reply_expecting(vec![StatusCodeKind::RequestActionCompleted])
+                .await?;
+        } else {
+            client.parse_reply().await?;
+        }
+
+        Ok(downloaded)
+    }
+
+    /// Get the maximum number of clients in use at once.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Get the number of idle clients waiting on the pool.
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().expect("Pool lock poisoned.").len()
+    }
+
+    fn take_idle(&self) -> Option<Client> {
+        self.idle.lock().expect("Pool lock poisoned.").pop()
+    }
+}
+
+/// A client handed out by a [Pool](struct.Pool.html), it
+/// dereferences to the client and goes back to the pool when dropped.
+pub struct PooledClient<'a> {
+    client: Option<Client>,
+    pool: &'a Pool,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl PooledClient<'_> {
+    /// Remove the client from the pool, such as after an error that
+    /// leaves it unusable. A new client is connected in its place.
+    pub fn discard(mut self) {
+        self.client = None;
+    }
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("Pooled client was discarded.")
+    }
+}
+
+impl DerefMut for PooledClient<'_> {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("Pooled client was discarded.")
+    }
+}
+
+impl Drop for PooledClient<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            if let Ok(mut idle) = self.pool.idle.lock() {
+                idle.push(client);
+            }
+        }
+    }
+}
+
+/// Run futures concurrently on the current task, returning their outputs in order.
+async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+
+    std::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_none() {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(value) => *output = Some(value),
+                    Poll::Pending => pending = true,
+                }
+            }
+        }
+
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+
+    outputs.into_iter().flatten().collect()
+}
@@ -0,0 +1,129 @@
+//! Contains the progress reporting of file transfers.
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A callback receiving the progress of file transfers.
+pub type ProgressCallback = Box<dyn FnMut(&Progress) + Send>;
+
+/// The progress of a file transfer, reported every
+/// time a chunk of data is transferred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    transferred: u64,
+    total: Option<u64>,
+    offset: u64,
+    elapsed: Duration,
+}
+
+impl Progress {
+    /// Get the number of bytes of the file transferred so far, including
+    /// the bytes skipped when the transfer was restarted at an offset.
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    /// Get the size of the file in bytes, if known. Downloads use the SIZE
+    /// command when supported by the server, uploads from byte buffers use
+    /// the buffer length and uploads from readers have no known size.
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// Get the time elapsed since the transfer started.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Get the average transfer rate in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            (self.transferred - self.offset) as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A data connection reporting the transfer progress to a callback,
+/// if any, as data is read or written.
+pub(crate) struct ProgressStream<'a, S> {
+    inner: S,
+    callback: Option<&'a mut ProgressCallback>,
+    started: Instant,
+    progress: Progress,
+}
+
+impl<'a, S> ProgressStream<'a, S> {
+    /// Track a transfer starting at an offset of a file with a possibly known size.
+    pub(crate) fn new(
+        inner: S,
+        callback: Option<&'a mut ProgressCallback>,
+        offset: u64,
+        total: Option<u64>,
+    ) -> Self {
+        ProgressStream {
+            inner,
+            callback,
+            started: Instant::now(),
+            progress: Progress {
+                transferred: offset,
+                total,
+                offset,
+                elapsed: Duration::default(),
+            },
+        }
+    }
+
+    fn report(&mut self, amount: usize) {
+        if let Some(callback) = self.callback.as_mut() {
+            self.progress.transferred += amount as u64;
+            self.progress.elapsed = self.started.elapsed();
+            callback(&self.progress);
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ProgressStream<'_, S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = poll {
+            if read > 0 {
+                this.report(read);
+            }
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ProgressStream<'_, S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            if written > 0 {
+                this.report(written);
+            }
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
@@ -15,6 +15,8 @@ pub enum StatusCodeKind {
     FeatureNotImplemented,
     /// Status code 211,
     SystemStatus,
+    /// Status code 213
+    FileStatus,
     /// Status code 214
     HelpMessage,
     /// Status code 215
@@ -23,10 +25,14 @@ pub enum StatusCodeKind {
     ReadyForNewUser,
     /// Status code 221
     ClosingControlConnection,
+    /// Status code 225
+    DataConnectionOpen,
     /// Status code 226
     RequestActionCompleted,
     /// Status code 230
     UserLoggedIn,
+    /// Status code 234
+    SecurityDataExchangeComplete,
     /// Status code 227
     EnteredPassiveMode,
     /// Status code 229
@@ -39,6 +45,8 @@ pub enum StatusCodeKind {
     PasswordRequired,
     /// Status code 350
     RequestActionPending,
+    /// Status code 421
+    ServiceNotAvailable,
     /// Status code 500
     CommandUnrecognized,
     /// Status code 504
@@ -60,18 +68,22 @@ impl From<u16> for StatusCodeKind {
             200 => StatusCodeKind::Ok,
             202 => StatusCodeKind::FeatureNotImplemented,
             211 => StatusCodeKind::SystemStatus,
+            213 => StatusCodeKind::FileStatus,
             214 => StatusCodeKind::HelpMessage,
             215 => StatusCodeKind::NameSystemType,
             221 => StatusCodeKind::ClosingControlConnection,
             220 => StatusCodeKind::ReadyForNewUser,
+            225 => StatusCodeKind::DataConnectionOpen,
             226 => StatusCodeKind::RequestActionCompleted,
             227 => StatusCodeKind::EnteredPassiveMode,
             229 => StatusCodeKind::EnteredExtendedPassiveMode,
             230 => StatusCodeKind::UserLoggedIn,
+            234 => StatusCodeKind::SecurityDataExchangeComplete,
             250 => StatusCodeKind::RequestFileActionCompleted,
             257 => StatusCodeKind::PathCreated,
             331 => StatusCodeKind::PasswordRequired,
             350 => StatusCodeKind::RequestActionPending,
+            421 => StatusCodeKind::ServiceNotAvailable,
             500 => StatusCodeKind::CommandUnrecognized,
             504 => StatusCodeKind::SecurityMechanismNotImplemented,
             550 => StatusCodeKind::RequestActionDenied,
@@ -1,7 +1,23 @@
 //! The blocking implementation of the client.
+use crate::checksum::FileHash;
 use crate::client::Client as AsyncClient;
-use crate::client::ClientMode;
+use crate::client::{ClientMode, ReconnectPolicy, Timeouts};
+use crate::client::{DataReader as AsyncDataReader, DataWriter as AsyncDataWriter};
+use crate::features::Features;
+use crate::listing::{Entry, Listing};
+use crate::progress::Progress;
+use crate::tree::{SyncDirection, SyncOptions, SyncReport, TreeReport};
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::runtime::Runtime;
+use tokio_rustls::rustls::ClientConfig;
 
 /// A wrapper over the async client.
 pub struct Client {
@@ -15,15 +31,291 @@ impl Client {
         self.inner_client.set_mode(mode)
     }
 
+    /// Set the local address to listen on for active mode data connections,
+    /// by default the address used by the control connection is used.
+    pub fn set_active_address(&mut self, address: IpAddr) {
+        self.inner_client.set_active_address(address)
+    }
+
+    /// Set the range of local ports to listen on for active mode data connections,
+    /// by default any port given by the operating system is used.
+    pub fn set_active_ports(&mut self, ports: RangeInclusive<u16>) {
+        self.inner_client.set_active_ports(ports)
+    }
+
+    /// Set a callback to receive the progress of file downloads and uploads,
+    /// such as the ones made by retrieve_file and store. The callback is
+    /// called every time a chunk of data is transferred.
+    ///
+    /// While a callback is set, downloads request the file size with
+    /// SIZE beforehand when the server supports it.
+    pub fn set_progress_callback<F: FnMut(&Progress) + Send + 'static>(&mut self, callback: F) {
+        self.inner_client.set_progress_callback(callback)
+    }
+
+    /// Stop reporting the progress of transfers.
+    pub fn clear_progress_callback(&mut self) {
+        self.inner_client.clear_progress_callback()
+    }
+
+    /// Limit the bandwidth used by both downloads and uploads, in bytes per second.
+    ///
+    /// The limit applies to every transfer made by the client, streaming or not.
+    /// A limit of 0 removes the limit.
+    pub fn set_rate_limit(&mut self, bytes_per_second: u64) {
+        self.inner_client.set_rate_limit(bytes_per_second)
+    }
+
+    /// Limit the bandwidth used by downloads, in bytes per second,
+    /// a limit of 0 removes the limit.
+    pub fn set_download_rate_limit(&mut self, bytes_per_second: u64) {
+        self.inner_client.set_download_rate_limit(bytes_per_second)
+    }
+
+    /// Limit the bandwidth used by uploads, in bytes per second,
+    /// a limit of 0 removes the limit.
+    pub fn set_upload_rate_limit(&mut self, bytes_per_second: u64) {
+        self.inner_client.set_upload_rate_limit(bytes_per_second)
+    }
+
+    /// Remove the bandwidth limits of downloads and uploads.
+    pub fn clear_rate_limit(&mut self) {
+        self.inner_client.clear_rate_limit()
+    }
+
+    /// Set the timeouts for connections, replies and data transfers.
+    ///
+    /// After a reply times out the control connection may be left in an
+    /// unknown state, so it is better to connect again.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.inner_client.set_timeouts(timeouts)
+    }
+
+    /// Get the timeouts for connections, replies and data transfers.
+    pub fn timeouts(&self) -> Timeouts {
+        self.inner_client.timeouts()
+    }
+
+    /// Send a NOOP on the control connection every interval while a file is
+    /// downloaded or uploaded, such as with retrieve_file and store, so the
+    /// control connection is not dropped for being idle during long transfers.
+    pub fn set_keepalive(&mut self, interval: Duration) {
+        self.inner_client.set_keepalive(interval)
+    }
+
+    /// Stop sending NOOP commands during transfers.
+    pub fn clear_keepalive(&mut self) {
+        self.inner_client.clear_keepalive()
+    }
+
+    /// Reconnect automatically when the server closes the control connection,
+    /// by replying 421 or dropping it. The client logs in again and restores the
+    /// working directory, transfer type and data channel protection of the session.
+    ///
+    /// Commands sent once the connection is lost reconnect first, but only commands
+    /// without side effects on the server, such as listings, downloads and directory
+    /// changes, are run again after reconnecting when the connection is lost while
+    /// they run. The policy is cleared on logout.
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.inner_client.set_reconnect_policy(policy)
+    }
+
+    /// Stop reconnecting automatically.
+    pub fn clear_reconnect_policy(&mut self) {
+        self.inner_client.clear_reconnect_policy()
+    }
+
+    /// Open a new control connection to the server, logging in again and restoring
+    /// the working directory, transfer type and data channel protection of the session.
+    /// The client mode and other settings are kept as they are.
+    pub fn reconnect(&mut self) -> Result<(), crate::error::Error> {
+        self.runtime.block_on(self.inner_client.reconnect())
+    }
+
     /// Connect to a new FTP server using plain text (no TLS).
     pub fn connect(
         hostname: &str,
         user: &str,
         password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        Self::connect_with_port(hostname, 21, user, password)
+    }
+
+    /// Connect to a new FTP server using plain text (no TLS) on a specific port.
+    pub fn connect_with_port(
+        hostname: &str,
+        port: u32,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let mut runtime = Runtime::new().unwrap();
+        let inner_client = runtime.block_on(AsyncClient::connect_with_port(
+            hostname, port, user, password,
+        ))?;
+
+        Ok(Client {
+            inner_client,
+            runtime,
+        })
+    }
+
+    /// Connect to a new FTP server using plain text (no TLS) on a specific port,
+    /// using the provided timeouts from the start of the connection.
+    pub fn connect_with_timeouts(
+        hostname: &str,
+        port: u32,
+        user: &str,
+        password: &str,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        let mut runtime = Runtime::new().unwrap();
+        let inner_client = runtime.block_on(AsyncClient::connect_with_timeouts(
+            hostname, port, user, password, timeouts,
+        ))?;
+
+        Ok(Client {
+            inner_client,
+            runtime,
+        })
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS), the control
+    /// connection is secured before the credentials are sent.
+    ///
+    /// The server certificate is verified against the hostname, which must be a
+    /// DNS name: IP addresses are rejected with a TlsError before connecting.
+    pub fn connect_secure(
+        hostname: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        Self::connect_secure_with_port(hostname, 21, user, password)
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS) on a specific port.
+    pub fn connect_secure_with_port(
+        hostname: &str,
+        port: u32,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let mut runtime = Runtime::new().unwrap();
+        let inner_client = runtime.block_on(AsyncClient::connect_secure_with_port(
+            hostname, port, user, password,
+        ))?;
+
+        Ok(Client {
+            inner_client,
+            runtime,
+        })
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS) on a specific port,
+    /// using the provided TLS configuration. This is useful to trust custom certificates.
+    pub fn connect_secure_with_config(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let mut runtime = Runtime::new().unwrap();
+        let inner_client = runtime.block_on(AsyncClient::connect_secure_with_config(
+            hostname, port, config, user, password,
+        ))?;
+
+        Ok(Client {
+            inner_client,
+            runtime,
+        })
+    }
+
+    /// Connect to a new FTP server using explicit TLS (AUTH TLS) on a specific port,
+    /// using the provided TLS configuration and timeouts.
+    pub fn connect_secure_with_timeouts(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+        timeouts: Timeouts,
+    ) -> Result<Self, crate::error::Error> {
+        let mut runtime = Runtime::new().unwrap();
+        let inner_client = runtime.block_on(AsyncClient::connect_secure_with_timeouts(
+            hostname, port, config, user, password, timeouts,
+        ))?;
+
+        Ok(Client {
+            inner_client,
+            runtime,
+        })
+    }
+
+    /// Connect to a new FTP server using implicit TLS, the TLS handshake is done
+    /// right after connecting, before the server greeting. Usually on port 990.
+    ///
+    /// The server certificate is verified against the hostname, which must be a
+    /// DNS name: IP addresses are rejected with a TlsError before connecting.
+    pub fn connect_implicit_secure(
+        hostname: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        Self::connect_implicit_secure_with_port(hostname, 990, user, password)
+    }
+
+    /// Connect to a new FTP server using implicit TLS on a specific port.
+    pub fn connect_implicit_secure_with_port(
+        hostname: &str,
+        port: u32,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let mut runtime = Runtime::new().unwrap();
+        let inner_client = runtime.block_on(AsyncClient::connect_implicit_secure_with_port(
+            hostname, port, user, password,
+        ))?;
+
+        Ok(Client {
+            inner_client,
+            runtime,
+        })
+    }
+
+    /// Connect to a new FTP server using implicit TLS on a specific port,
+    /// using the provided TLS configuration. This is useful to trust custom certificates.
+    pub fn connect_implicit_secure_with_config(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::error::Error> {
+        let mut runtime = Runtime::new().unwrap();
+        let inner_client = runtime.block_on(AsyncClient::connect_implicit_secure_with_config(
+            hostname, port, config, user, password,
+        ))?;
+
+        Ok(Client {
+            inner_client,
+            runtime,
+        })
+    }
+
+    /// Connect to a new FTP server using implicit TLS on a specific port,
+    /// using the provided TLS configuration and timeouts.
+    pub fn connect_implicit_secure_with_timeouts(
+        hostname: &str,
+        port: u32,
+        config: Arc<ClientConfig>,
+        user: &str,
+        password: &str,
+        timeouts: Timeouts,
     ) -> Result<Self, crate::error::Error> {
         let mut runtime = Runtime::new().unwrap();
-        let inner_client =
-            runtime.block_on(AsyncClient::connect_with_port(hostname, 21, user, password))?;
+        let inner_client = runtime.block_on(AsyncClient::connect_implicit_secure_with_timeouts(
+            hostname, port, config, user, password, timeouts,
+        ))?;
 
         Ok(Client {
             inner_client,
@@ -36,6 +328,20 @@ impl Client {
         self.inner_client.get_welcome()
     }
 
+    /// Protect the data connections with TLS, using the PBSZ and PROT commands
+    /// described on RFC4217. This is done automatically for secure connections.
+    pub fn protect_data_channel(&mut self) -> Result<(), crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.protect_data_channel())
+    }
+
+    /// Stop protecting the data connections with TLS, data will be sent as plain text
+    /// while the control connection stays secure.
+    pub fn clear_data_channel(&mut self) -> Result<(), crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.clear_data_channel())
+    }
+
     /// Login using the given user and password.
     /// Note that many servers require a login with an anonymous user,
     /// such as client.login("anonymous", "anonymous@mail.com").
@@ -45,6 +351,9 @@ impl Client {
     }
 
     /// Logout from the current user/password pair.
+    ///
+    /// The server closes the connection on QUIT, so the credentials and the reconnect
+    /// policy are cleared, keeping the next commands from logging in again.
     pub fn logout(&mut self) -> Result<(), crate::error::Error> {
         self.runtime.block_on(self.inner_client.logout())
     }
@@ -68,6 +377,14 @@ impl Client {
         self.runtime.block_on(self.inner_client.help(command))
     }
 
+    /// Get the features supported by the server, using the FEAT command.
+    ///
+    /// The features are requested once and cached on the client, servers
+    /// that do not implement FEAT are treated as having no features.
+    pub fn features(&mut self) -> Result<&Features, crate::error::Error> {
+        self.runtime.block_on(self.inner_client.features())
+    }
+
     /// This command should not do anything other than receiving
     /// an OK response from the server.
     pub fn noop(&mut self) -> Result<(), crate::error::Error> {
@@ -102,6 +419,29 @@ impl Client {
         self.runtime.block_on(self.inner_client.list_names(path))
     }
 
+    /// List the provided path with LIST and parse the output into entries, this works
+    /// with servers that do not support MLSD, as long as they use the Unix or DOS format.
+    ///
+    /// Lines that can not be parsed are kept on the listing, instead of being dropped.
+    pub fn list_entries(&mut self, path: &str) -> Result<Listing, crate::error::Error> {
+        self.runtime.block_on(self.inner_client.list_entries(path))
+    }
+
+    /// List the provided path using MLSD, getting structured information
+    /// about each file and directory, as described on RFC3659.
+    ///
+    /// An empty path lists the current working directory. Some servers
+    /// also list the directory itself and its parent, check the entry kind.
+    pub fn mlsd(&mut self, path: &str) -> Result<Vec<Entry>, crate::error::Error> {
+        self.runtime.block_on(self.inner_client.mlsd(path))
+    }
+
+    /// Get structured information about a single file or directory using MLST,
+    /// as described on RFC3659. The entry name is the full path sent by the server.
+    pub fn mlst(&mut self, path: &str) -> Result<Entry, crate::error::Error> {
+        self.runtime.block_on(self.inner_client.mlst(path))
+    }
+
     /// Store a new file on a provided path and name.
     pub fn store<B: AsRef<[u8]>>(
         &mut self,
@@ -111,11 +451,30 @@ impl Client {
         self.runtime.block_on(self.inner_client.store(path, data))
     }
 
+    /// Store a new file on a provided path and name, reading its contents from a reader.
+    /// The data is copied in chunks so it is never fully loaded in memory.
+    /// Returns the number of bytes uploaded.
+    pub fn store_from<R: Read>(
+        &mut self,
+        path: &str,
+        reader: R,
+    ) -> Result<u64, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.store_from(path, BlockingReader(reader)))
+    }
+
     /// Store a new file on a provided path using a random unique name.
     pub fn store_unique<B: AsRef<[u8]>>(&mut self, data: B) -> Result<String, crate::error::Error> {
         self.runtime.block_on(self.inner_client.store_unique(data))
     }
 
+    /// Store a new file on a provided path using a random unique name,
+    /// reading its contents from a reader.
+    pub fn store_unique_from<R: Read>(&mut self, reader: R) -> Result<String, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.store_unique_from(BlockingReader(reader)))
+    }
+
     /// Append to a existing file or a create a new one.
     pub fn append<B: AsRef<[u8]>>(
         &mut self,
@@ -125,14 +484,51 @@ impl Client {
         self.runtime.block_on(self.inner_client.append(path, data))
     }
 
-    /// Restart a file transfer. Unimplemented.
-    pub fn restart(&mut self) -> Result<(), crate::error::Error> {
-        unimplemented!();
+    /// Append to a existing file or a create a new one, reading the data from a reader.
+    /// Returns the number of bytes uploaded.
+    pub fn append_from<R: Read>(
+        &mut self,
+        path: &str,
+        reader: R,
+    ) -> Result<u64, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.append_from(path, BlockingReader(reader)))
     }
 
-    /// Abort a file transfer. Unimplemented.
-    pub fn abort(&mut self) -> Result<(), crate::error::Error> {
-        unimplemented!();
+    /// Restart a file transfer at a byte offset, using the REST command.
+    /// The offset applies to the next transfer command, such as RETR.
+    ///
+    /// REST is sent right before the transfer command, after the data
+    /// connection is set up, as some servers reset the offset on PASV or PORT.
+    /// Offsets are only meaningful on binary mode.
+    pub fn restart(&mut self, offset: u64) -> Result<(), crate::error::Error> {
+        self.runtime.block_on(self.inner_client.restart(offset))
+    }
+
+    /// Download a file at a path into a byte buffer, starting at a byte offset.
+    ///
+    /// The offset is validated against the file size when the server supports SIZE.
+    pub fn retrieve_file_from(
+        &mut self,
+        path: &str,
+        offset: u64,
+    ) -> Result<Vec<u8>, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.retrieve_file_from(path, offset))
+    }
+
+    /// Resume the download of a file into a local file, appending the bytes
+    /// missing locally, the local file is created if it does not exist.
+    /// Returns the number of bytes downloaded.
+    ///
+    /// The local size is validated against the file size when the server supports SIZE.
+    pub fn resume_download<P: AsRef<Path>>(
+        &mut self,
+        path: &str,
+        local_path: P,
+    ) -> Result<u64, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.resume_download(path, local_path))
     }
 
     /// Preallocate space on the server. Unimplemented.
@@ -200,8 +596,249 @@ impl Client {
             .block_on(self.inner_client.delete_file(dir_path))
     }
 
+    /// Get the size of a file in bytes, using the SIZE command.
+    ///
+    /// Note that the size reported on ASCII mode may differ from the
+    /// size of the file on disk, use binary mode for exact sizes.
+    pub fn size(&mut self, path: &str) -> Result<u64, crate::error::Error> {
+        self.runtime.block_on(self.inner_client.size(path))
+    }
+
+    /// Get the last modification time of a file, using the MDTM command.
+    pub fn modified(&mut self, path: &str) -> Result<SystemTime, crate::error::Error> {
+        self.runtime.block_on(self.inner_client.modified(path))
+    }
+
+    /// Get the checksum of a file, using the HASH command with the algorithm
+    /// currently selected on the server. The algorithms supported by the server
+    /// are advertised on [features](struct.Client.html#method.features).
+    pub fn hash(&mut self, path: &str) -> Result<FileHash, crate::error::Error> {
+        self.runtime.block_on(self.inner_client.hash(path))
+    }
+
+    /// Select the algorithm used by the HASH command, such as "MD5" or "SHA-256".
+    pub fn set_hash_algorithm(&mut self, algorithm: &str) -> Result<(), crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.set_hash_algorithm(algorithm))
+    }
+
     /// Download a file at a path into a byte buffer.
     pub fn retrieve_file(&mut self, path: &str) -> Result<Vec<u8>, crate::error::Error> {
         self.runtime.block_on(self.inner_client.retrieve_file(path))
     }
+
+    /// Download a file at a path into a writer, the data is copied in chunks
+    /// so the file is never fully loaded in memory. Returns the number of bytes downloaded.
+    pub fn retrieve_to<W: Write>(
+        &mut self,
+        path: &str,
+        writer: W,
+    ) -> Result<u64, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.retrieve_to(path, BlockingWriter(writer)))
+    }
+
+    /// Open a file at a path for reading, the returned handle reads
+    /// the file straight from the data connection.
+    pub fn open_read(&mut self, path: &str) -> Result<DataReader<'_>, crate::error::Error> {
+        let inner = self.runtime.block_on(self.inner_client.open_read(path))?;
+        Ok(DataReader {
+            inner,
+            runtime: &mut self.runtime,
+        })
+    }
+
+    /// Open a file at a path for writing, the returned handle writes
+    /// the file straight into the data connection.
+    pub fn open_write(&mut self, path: &str) -> Result<DataWriter<'_>, crate::error::Error> {
+        let inner = self.runtime.block_on(self.inner_client.open_write(path))?;
+        Ok(DataWriter {
+            inner,
+            runtime: &mut self.runtime,
+        })
+    }
+
+    /// Download a remote directory and everything under it into a local
+    /// directory, recreating the hierarchy and streaming each file to disk
+    /// in binary mode. Existing local files are overwritten.
+    ///
+    /// Directories are listed with MLSD when supported by the server and with
+    /// LIST otherwise. A failure on a file or subdirectory is recorded on the
+    /// report and the download goes on, only failing to list the remote
+    /// directory or to create the local one returns an error.
+    pub fn download_tree<P: AsRef<Path>>(
+        &mut self,
+        remote_dir: &str,
+        local_dir: P,
+    ) -> Result<TreeReport, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.download_tree(remote_dir, local_dir))
+    }
+
+    /// Upload a local directory and everything under it into a remote
+    /// directory, keeping the relative layout and uploading each file in
+    /// binary mode. Missing remote directories are created and existing
+    /// remote files are overwritten. Symbolic links are not followed, they
+    /// are reported as skipped so a link cycle can not loop the upload.
+    ///
+    /// A failure on a file or subdirectory is recorded on the report and
+    /// the upload goes on, only failing to read the local directory or to
+    /// find or create the remote one returns an error.
+    pub fn upload_tree<P: AsRef<Path>>(
+        &mut self,
+        local_dir: P,
+        remote_dir: &str,
+    ) -> Result<TreeReport, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.upload_tree(local_dir, remote_dir))
+    }
+
+    /// Sync a local and a remote tree, transferring from the source to the
+    /// destination only the files that are new or changed, as compared with
+    /// the [options](../tree/struct.SyncOptions.html). Files are transferred in
+    /// binary mode and the destination root is created if missing.
+    ///
+    /// Both trees are listed before anything is changed, failing to list
+    /// them returns an error. Local symbolic links are not followed and are
+    /// left out of the sync. A failure on a step is recorded on the report
+    /// and the sync goes on. On a dry run the plan is logged and returned
+    /// without changing any file, it can also be printed with Display.
+    pub fn sync_tree<P: AsRef<Path>>(
+        &mut self,
+        local_dir: P,
+        remote_dir: &str,
+        direction: SyncDirection,
+        options: SyncOptions,
+    ) -> Result<SyncReport, crate::error::Error> {
+        self.runtime.block_on(
+            self.inner_client
+                .sync_tree(local_dir, remote_dir, direction, options),
+        )
+    }
+
+    /// Delete a remote directory and everything under it. The tree is listed
+    /// first, then files and other entries, such as symbolic links, are deleted
+    /// with DELE and directories are removed with RMD, deepest first, ending with
+    /// the directory itself. Entries whose names could escape the tree, such as
+    /// names with a slash, are reported as skipped.
+    ///
+    /// Entries that could not be deleted are recorded on the report and the
+    /// delete goes on, unless stop_on_error is set, in which case the report
+    /// ends at the first failure. Failing to list the tree returns an error.
+    pub fn remove_tree(
+        &mut self,
+        remote_dir: &str,
+        stop_on_error: bool,
+    ) -> Result<TreeReport, crate::error::Error> {
+        self.runtime
+            .block_on(self.inner_client.remove_tree(remote_dir, stop_on_error))
+    }
+}
+
+/// A handle to read a file being downloaded, over the live data connection.
+///
+/// The final reply is read when the handle is finished. If it is dropped
+/// instead, the reply is read before the next command sent by the client.
+pub struct DataReader<'a> {
+    inner: AsyncDataReader<'a>,
+    runtime: &'a mut Runtime,
+}
+
+impl DataReader<'_> {
+    /// Close the data connection and read the final reply to the transfer.
+    ///
+    /// Finishing before the whole file is read interrupts the transfer,
+    /// which most servers report with an error reply.
+    pub fn finish(self) -> Result<(), crate::error::Error> {
+        self.runtime.block_on(self.inner.finish())
+    }
+
+    /// Abort the transfer with ABOR, closing the data connection and
+    /// reading every reply to the transfer and to ABOR.
+    pub fn abort(self) -> Result<(), crate::error::Error> {
+        self.runtime.block_on(self.inner.abort())
+    }
+}
+
+impl Read for DataReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.runtime.block_on(self.inner.read(buf))
+    }
+}
+
+/// A handle to write a file being uploaded, over the live data connection.
+///
+/// The final reply is read when the handle is finished. If it is dropped
+/// instead, the reply is read before the next command sent by the client.
+pub struct DataWriter<'a> {
+    inner: AsyncDataWriter<'a>,
+    runtime: &'a mut Runtime,
+}
+
+impl DataWriter<'_> {
+    /// Shutdown the data connection, signaling the end of the file,
+    /// and read the final reply to the transfer.
+    pub fn finish(self) -> Result<(), crate::error::Error> {
+        self.runtime.block_on(self.inner.finish())
+    }
+
+    /// Abort the transfer with ABOR, closing the data connection and
+    /// reading every reply to the transfer and to ABOR.
+    ///
+    /// Servers may keep the part of the file already uploaded.
+    pub fn abort(self) -> Result<(), crate::error::Error> {
+        self.runtime.block_on(self.inner.abort())
+    }
+}
+
+impl Write for DataWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.runtime.block_on(self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.runtime.block_on(self.inner.flush())
+    }
+}
+
+/// Adapt a blocking writer to be used by the async client, writes
+/// simply block the runtime owned by the sync client.
+struct BlockingWriter<W>(W);
+
+// The writer is never pinned, so the wrapper can be moved freely.
+impl<W> Unpin for BlockingWriter<W> {}
+
+impl<W: Write> AsyncWrite for BlockingWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(self.get_mut().0.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(self.get_mut().0.flush())
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Adapt a blocking reader to be used by the async client, reads
+/// simply block the runtime owned by the sync client.
+struct BlockingReader<R>(R);
+
+// The reader is never pinned, so the wrapper can be moved freely.
+impl<R> Unpin for BlockingReader<R> {}
+
+impl<R: Read> AsyncRead for BlockingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(self.get_mut().0.read(buf))
+    }
 }
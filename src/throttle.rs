@@ -0,0 +1,116 @@
+//! Contains the bandwidth throttling of data connections.
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::Delay;
+
+/// A data connection limited to a number of bytes per second, if any limit
+/// is set. The rate is averaged since the start of the transfer, so each read
+/// or write is delayed until the bytes transferred so far are within the limit.
+pub struct ThrottledStream<S> {
+    inner: S,
+    bytes_per_second: Option<u64>,
+    started: Instant,
+    transferred: u64,
+    delay: Option<Delay>,
+}
+
+impl<S> ThrottledStream<S> {
+    /// Limit a connection to a number of bytes per second, no
+    /// limit is applied if bytes_per_second is None or 0.
+    pub fn new(inner: S, bytes_per_second: Option<u64>) -> Self {
+        ThrottledStream {
+            inner,
+            bytes_per_second: bytes_per_second.filter(|&rate| rate > 0),
+            started: Instant::now(),
+            transferred: 0,
+            delay: None,
+        }
+    }
+
+    /// Wait until the previous transfers are within the limit.
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(delay) = self.delay.as_mut() {
+            if Pin::new(delay).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+
+        Poll::Ready(())
+    }
+
+    /// Get the most bytes allowed on a single read or write, so a large
+    /// buffer can not go much over the limit at once.
+    fn chunk_size(&self, len: usize) -> usize {
+        match self.bytes_per_second {
+            Some(rate) => len.min((rate / 4).max(1) as usize),
+            None => len,
+        }
+    }
+
+    /// Account for transferred bytes, delaying the next transfer if needed.
+    fn record(&mut self, amount: usize) {
+        let rate = match self.bytes_per_second {
+            Some(rate) => rate,
+            None => return,
+        };
+
+        self.transferred += amount as u64;
+        let allowed_at =
+            self.started + Duration::from_secs_f64(self.transferred as f64 / rate as f64);
+        if allowed_at > Instant::now() {
+            self.delay = Some(tokio::time::delay_until(allowed_at.into()));
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ThrottledStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if this.poll_delay(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let len = this.chunk_size(buf.len());
+        let poll = Pin::new(&mut this.inner).poll_read(cx, &mut buf[..len]);
+        if let Poll::Ready(Ok(read)) = poll {
+            this.record(read);
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ThrottledStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if this.poll_delay(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let len = this.chunk_size(buf.len());
+        let poll = Pin::new(&mut this.inner).poll_write(cx, &buf[..len]);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.record(written);
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
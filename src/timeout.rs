@@ -0,0 +1,118 @@
+//! Contains the inactivity timeout of data connections.
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::Delay;
+
+/// A data connection failing with a TimedOut IO error when a read or write
+/// makes no progress for a duration, if any timeout is set.
+pub struct TimeoutStream<S> {
+    inner: S,
+    timeout: Option<Duration>,
+    delay: Option<Delay>,
+}
+
+impl<S> TimeoutStream<S> {
+    /// Limit the inactivity of a connection to a duration, no
+    /// timeout is applied if timeout is None.
+    pub fn new(inner: S, timeout: Option<Duration>) -> Self {
+        TimeoutStream {
+            inner,
+            timeout,
+            delay: None,
+        }
+    }
+
+    /// Start counting the inactivity when the connection is pending,
+    /// restarting the count once it makes progress.
+    fn check<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<std::io::Result<T>>,
+    ) -> Poll<std::io::Result<T>> {
+        let timeout = match (&poll, self.timeout) {
+            (Poll::Pending, Some(timeout)) => timeout,
+            _ => {
+                self.delay = None;
+                return poll;
+            }
+        };
+
+        let delay = self
+            .delay
+            .get_or_insert_with(|| tokio::time::delay_for(timeout));
+        match Pin::new(delay).poll(cx) {
+            Poll::Ready(()) => {
+                self.delay = None;
+                Poll::Ready(Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("No data was transferred for {:?}.", timeout),
+                )))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TimeoutStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        this.check(cx, poll)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TimeoutStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        this.check(cx, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_flush(cx);
+        this.check(cx, poll)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_shutdown(cx);
+        this.check(cx, poll)
+    }
+}
+
+/// Run a future with an optional timeout, failing with a
+/// [Timeout](../error/enum.Error.html#variant.Timeout) error once it elapses.
+pub async fn with_timeout<T, E, F>(
+    timeout: Option<Duration>,
+    action: &str,
+    future: F,
+) -> Result<T, crate::error::Error>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<crate::error::Error>,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return future.await.map_err(Into::into),
+    };
+
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(crate::error::Error::Timeout(format!(
+            "{} timed out after {:?}.",
+            action, timeout
+        ))),
+    }
+}
@@ -0,0 +1,937 @@
+//! Contains the recursive operations on directory trees, such as
+//! downloading a whole remote directory, syncing a local and a
+//! remote tree or deleting a remote tree, and the reports they return.
+use crate::client::Client;
+use crate::listing::{EntryKind, Listing};
+use log::info;
+use md5::{Digest, Md5};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// What happened to a single file on a recursive operation.
+#[derive(Debug)]
+pub enum FileStatus {
+    /// The file was transferred, with the number of bytes transferred.
+    Transferred(u64),
+    /// The file was left untouched, with the reason why.
+    Skipped(String),
+    /// The file or directory was deleted.
+    Deleted,
+    /// The operation failed on the file, or on the directory containing it.
+    Failed(crate::error::Error),
+}
+
+/// The outcome of a recursive operation for a single file or directory.
+#[derive(Debug)]
+pub struct FileReport {
+    /// The path of the file on the server.
+    pub remote_path: String,
+    /// The path of the file on the local machine,
+    /// empty on operations that only change the server.
+    pub local_path: PathBuf,
+    /// What happened to the file.
+    pub status: FileStatus,
+}
+
+/// The report of a recursive operation, with an entry for every file
+/// found and for every directory that could not be handled.
+#[derive(Debug, Default)]
+pub struct TreeReport {
+    /// The outcome for each file, on the order they were handled.
+    pub files: Vec<FileReport>,
+}
+
+impl TreeReport {
+    /// Get the files that were transferred.
+    pub fn transferred(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.status, FileStatus::Transferred(_)))
+    }
+
+    /// Get the files that were left untouched.
+    pub fn skipped(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.status, FileStatus::Skipped(_)))
+    }
+
+    /// Get the files and directories the operation failed on.
+    pub fn failed(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.status, FileStatus::Failed(_)))
+    }
+
+    /// Get the files and directories that were deleted.
+    pub fn deleted(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.status, FileStatus::Deleted))
+    }
+
+    /// Returns whether the operation did not fail on any file.
+    pub fn is_success(&self) -> bool {
+        self.failed().next().is_none()
+    }
+
+    /// Get the total number of bytes transferred.
+    pub fn bytes_transferred(&self) -> u64 {
+        self.files
+            .iter()
+            .map(|file| match file.status {
+                FileStatus::Transferred(bytes) => bytes,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn push(&mut self, remote_path: &str, local_path: &Path, status: FileStatus) {
+        self.files.push(FileReport {
+            remote_path: remote_path.to_string(),
+            local_path: local_path.to_path_buf(),
+            status,
+        });
+    }
+
+    /// Report the lines of a directory listing that could not be parsed as
+    /// skipped, with the line as the reason.
+    fn push_unparsed(&mut self, remote_dir: &str, local_dir: &Path, lines: Vec<String>) {
+        for line in lines {
+            self.push(remote_dir, local_dir, FileStatus::Skipped(line));
+        }
+    }
+}
+
+/// The direction of a sync, from the source tree to the destination tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncDirection {
+    /// Update the local tree from the remote tree.
+    Download,
+    /// Update the remote tree from the local tree.
+    Upload,
+}
+
+/// How a file on the source is compared to the file on the destination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    /// The file changed if the sizes differ or if the source
+    /// was modified after the destination.
+    SizeAndTime,
+    /// The file changed if the sizes or the MD5 checksums differ, using the
+    /// HASH command. Servers that do not support MD5 on HASH, and files the
+    /// server fails to hash, are compared by size and modification time instead.
+    Checksum,
+}
+
+/// The options of a sync between a local and a remote tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncOptions {
+    /// How files on both trees are compared.
+    pub comparison: Comparison,
+    /// Delete the files and directories of the destination missing on the source.
+    pub delete: bool,
+    /// Only plan the sync, logging the plan without changing any file.
+    pub dry_run: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            comparison: Comparison::SizeAndTime,
+            delete: false,
+            dry_run: false,
+        }
+    }
+}
+
+/// What a sync does to a single file or directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncAction {
+    /// Create a directory missing on the destination.
+    CreateDirectory,
+    /// Transfer a file missing on the destination.
+    TransferNew,
+    /// Transfer a file that changed on the source.
+    TransferChanged,
+    /// Leave a file that did not change untouched.
+    Unchanged,
+    /// Delete a file missing on the source.
+    Delete,
+    /// Delete a directory missing on the source.
+    DeleteDirectory,
+}
+
+/// A single step of a sync plan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncStep {
+    /// What is done to the file or directory.
+    pub action: SyncAction,
+    /// The path of the file or directory on the server.
+    pub remote_path: String,
+    /// The path of the file or directory on the local machine.
+    pub local_path: PathBuf,
+}
+
+/// The steps of a sync, on the order they are carried out: missing directories
+/// are created first, then files are transferred, then files and directories
+/// missing on the source are deleted, deepest first.
+///
+/// The plan is displayed with one line for every step that changes a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncPlan {
+    /// The direction of the sync.
+    pub direction: SyncDirection,
+    /// The steps of the sync, including the unchanged files.
+    pub steps: Vec<SyncStep>,
+}
+
+impl SyncPlan {
+    /// Returns whether the sync has nothing to change.
+    pub fn is_empty(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.action == SyncAction::Unchanged)
+    }
+
+    fn push(&mut self, action: SyncAction, remote_path: String, local_path: PathBuf) {
+        self.steps.push(SyncStep {
+            action,
+            remote_path,
+            local_path,
+        });
+    }
+}
+
+impl fmt::Display for SyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.direction {
+            SyncDirection::Download => "download",
+            SyncDirection::Upload => "upload",
+        };
+        for step in &self.steps {
+            let local_path = step.local_path.display().to_string();
+            let (source, destination) = match self.direction {
+                SyncDirection::Download => (&step.remote_path, &local_path),
+                SyncDirection::Upload => (&local_path, &step.remote_path),
+            };
+            match step.action {
+                SyncAction::CreateDirectory => writeln!(f, "create directory {}", destination)?,
+                SyncAction::TransferNew => {
+                    writeln!(f, "{} {} -> {} (new)", verb, source, destination)?
+                }
+                SyncAction::TransferChanged => {
+                    writeln!(f, "{} {} -> {} (changed)", verb, source, destination)?
+                }
+                SyncAction::Unchanged => {}
+                SyncAction::Delete => writeln!(f, "delete {}", destination)?,
+                SyncAction::DeleteDirectory => writeln!(f, "delete directory {}", destination)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The report of a sync, with the plan and the outcome of each step.
+#[derive(Debug)]
+pub struct SyncReport {
+    /// The planned steps of the sync.
+    pub plan: SyncPlan,
+    /// The outcome for each file, empty on a dry run except for the remote
+    /// listing lines that could not be parsed. Directories are only listed
+    /// if they were deleted or could not be handled.
+    pub files: TreeReport,
+}
+
+/// The files and directories of a tree, by their path relative to the root.
+#[derive(Debug, Default)]
+struct Snapshot {
+    files: BTreeMap<String, FileInfo>,
+    directories: BTreeSet<String>,
+    /// Entries that are neither files nor directories, such as symbolic links.
+    others: BTreeSet<String>,
+    /// The full paths of remote entries whose names could escape the tree.
+    unsafe_paths: Vec<String>,
+    /// The remote listing lines that could not be parsed, with the
+    /// directory they were listed on, relative to the root.
+    unparsed: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FileInfo {
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+}
+
+impl Client {
+    /// Download a remote directory and everything under it into a local
+    /// directory, recreating the hierarchy and streaming each file to disk
+    /// in binary mode. Existing local files are overwritten.
+    ///
+    /// Directories are listed with MLSD when supported by the server and with
+    /// LIST otherwise, LIST lines that could not be parsed are reported as skipped.
+    /// A failure on a file or subdirectory is recorded on the report and the
+    /// download goes on, only failing to list the remote directory or to create
+    /// the local one returns an error.
+    pub async fn download_tree<P: AsRef<Path>>(
+        &mut self,
+        remote_dir: &str,
+        local_dir: P,
+    ) -> Result<TreeReport, crate::error::Error> {
+        self.binary().await?;
+        let mut report = TreeReport::default();
+        let root = (remote_dir.to_string(), local_dir.as_ref().to_path_buf());
+        let listing = self.list_directory(&root.0).await?;
+        tokio::fs::create_dir_all(&root.1).await?;
+
+        let mut pending = VecDeque::new();
+        pending.push_back((root, Some(listing)));
+        while let Some(((remote_dir, local_dir), listing)) = pending.pop_front() {
+            let listing = match listing {
+                Some(listing) => listing,
+                None => match self.list_directory(&remote_dir).await {
+                    Ok(listing) => listing,
+                    Err(error) => {
+                        report.push(&remote_dir, &local_dir, FileStatus::Failed(error));
+                        continue;
+                    }
+                },
+            };
+            if let Err(error) = tokio::fs::create_dir_all(&local_dir).await {
+                report.push(&remote_dir, &local_dir, FileStatus::Failed(error.into()));
+                continue;
+            }
+            report.push_unparsed(&remote_dir, &local_dir, listing.unparsed);
+
+            for entry in listing.entries {
+                let remote_path = join_remote(&remote_dir, &entry.name);
+                let local_path = local_dir.join(&entry.name);
+                if !is_safe_name(&entry.name) {
+                    let reason = "The name is not a valid local file name.".to_string();
+                    report.push(&remote_path, &local_path, FileStatus::Skipped(reason));
+                } else if entry.is_directory() {
+                    pending.push_back(((remote_path, local_path), None));
+                } else if entry.is_file() {
+                    let status = match self.download_tree_file(&remote_path, &local_path).await {
+                        Ok(bytes) => FileStatus::Transferred(bytes),
+                        Err(error) => FileStatus::Failed(error),
+                    };
+                    report.push(&remote_path, &local_path, status);
+                } else {
+                    let reason = format!("Not a regular file or directory: {:?}.", entry.kind);
+                    report.push(&remote_path, &local_path, FileStatus::Skipped(reason));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Upload a local directory and everything under it into a remote
+    /// directory, keeping the relative layout and uploading each file in
+    /// binary mode. Missing remote directories are created and existing
+    /// remote files are overwritten. Symbolic links are not followed, they
+    /// are reported as skipped so a link cycle can not loop the upload, as are
+    /// the lines of remote LIST outputs that could not be parsed.
+    ///
+    /// A failure on a file or subdirectory is recorded on the report and
+    /// the upload goes on, only failing to read the local directory or to
+    /// find or create the remote one returns an error.
+    pub async fn upload_tree<P: AsRef<Path>>(
+        &mut self,
+        local_dir: P,
+        remote_dir: &str,
+    ) -> Result<TreeReport, crate::error::Error> {
+        self.binary().await?;
+        let mut report = TreeReport::default();
+        let root = (local_dir.as_ref().to_path_buf(), remote_dir.to_string());
+        let files = read_local_dir(&root.0).await?;
+        let existing = match self.list_root(&root.1).await? {
+            Some(listing) => listing,
+            None => {
+                self.make_directory(&root.1).await?;
+                Listing::default()
+            }
+        };
+
+        let mut root_files = Some(files);
+        let mut pending = VecDeque::new();
+        pending.push_back((root, Some(existing)));
+        while let Some(((local_dir, remote_dir), existing)) = pending.pop_front() {
+            let files = match root_files.take() {
+                Some(files) => Ok(files),
+                None => read_local_dir(&local_dir).await,
+            };
+            let existing = match existing {
+                Some(existing) => Ok(existing),
+                // The remote directory is only listed if it already existed.
+                None => self.list_directory(&remote_dir).await,
+            };
+            let (files, existing) = match (files, existing) {
+                (Ok(files), Ok(existing)) => (files, existing),
+                (Err(error), _) | (_, Err(error)) => {
+                    report.push(&remote_dir, &local_dir, FileStatus::Failed(error));
+                    continue;
+                }
+            };
+            report.push_unparsed(&remote_dir, &local_dir, existing.unparsed);
+
+            for (name, local_path) in files {
+                let name = match name {
+                    Some(name) => name,
+                    None => {
+                        let reason = "The name is not valid UTF-8.".to_string();
+                        let remote_path = local_path.to_string_lossy();
+                        report.push(&remote_path, &local_path, FileStatus::Skipped(reason));
+                        continue;
+                    }
+                };
+                let remote_path = join_remote(&remote_dir, &name);
+                let metadata = match tokio::fs::symlink_metadata(&local_path).await {
+                    Ok(metadata) => metadata,
+                    Err(error) => {
+                        report.push(&remote_path, &local_path, FileStatus::Failed(error.into()));
+                        continue;
+                    }
+                };
+
+                if metadata.file_type().is_symlink() {
+                    let reason = "Symbolic links are not followed.".to_string();
+                    report.push(&remote_path, &local_path, FileStatus::Skipped(reason));
+                } else if metadata.is_dir() {
+                    let exists = existing
+                        .entries
+                        .iter()
+                        .any(|entry| entry.name == name && entry.is_directory());
+                    if exists {
+                        pending.push_back(((local_path, remote_path), None));
+                    } else if let Err(error) = self.make_directory(&remote_path).await {
+                        report.push(&remote_path, &local_path, FileStatus::Failed(error));
+                    } else {
+                        pending.push_back(((local_path, remote_path), Some(Listing::default())));
+                    }
+                } else if metadata.is_file() {
+                    let status = match self.upload_tree_file(&local_path, &remote_path).await {
+                        Ok(bytes) => FileStatus::Transferred(bytes),
+                        Err(error) => FileStatus::Failed(error),
+                    };
+                    report.push(&remote_path, &local_path, status);
+                } else {
+                    let reason = "Not a regular file or directory.".to_string();
+                    report.push(&remote_path, &local_path, FileStatus::Skipped(reason));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Sync a local and a remote tree, transferring from the source to the
+    /// destination only the files that are new or changed, as compared with
+    /// the [options](../tree/struct.SyncOptions.html). Files are transferred in
+    /// binary mode and the destination root is created if missing.
+    ///
+    /// Both trees are listed before anything is changed, failing to list
+    /// them returns an error. Local symbolic links are not followed and are
+    /// left out of the sync, remote LIST lines that could not be parsed are
+    /// reported as skipped. A failure on a step is recorded on the report
+    /// and the sync goes on. On a dry run the plan is logged and returned
+    /// without changing any file, it can also be printed with Display.
+    pub async fn sync_tree<P: AsRef<Path>>(
+        &mut self,
+        local_dir: P,
+        remote_dir: &str,
+        direction: SyncDirection,
+        options: SyncOptions,
+    ) -> Result<SyncReport, crate::error::Error> {
+        let local_dir = local_dir.as_ref();
+        let mut report = TreeReport::default();
+        let plan = self
+            .plan_sync(local_dir, remote_dir, direction, options, &mut report)
+            .await?;
+        if options.dry_run {
+            for line in plan.to_string().lines() {
+                info!("Dry run: {}", line);
+            }
+            return Ok(SyncReport {
+                plan,
+                files: report,
+            });
+        }
+
+        for step in &plan.steps {
+            let status = self.run_sync_step(step, direction).await;
+            if let Some(status) = status {
+                report.push(&step.remote_path, &step.local_path, status);
+            }
+        }
+
+        Ok(SyncReport {
+            plan,
+            files: report,
+        })
+    }
+
+    /// Delete a remote directory and everything under it. The tree is listed
+    /// first, then files and other entries, such as symbolic links, are deleted
+    /// with DELE and directories are removed with RMD, deepest first, ending with
+    /// the directory itself. Entries whose names could escape the tree, such as
+    /// names with a slash, and LIST lines that could not be parsed are reported
+    /// as skipped.
+    ///
+    /// Entries that could not be deleted are recorded on the report and the
+    /// delete goes on, unless stop_on_error is set, in which case the report
+    /// ends at the first failure. Failing to list the tree returns an error.
+    pub async fn remove_tree(
+        &mut self,
+        remote_dir: &str,
+        stop_on_error: bool,
+    ) -> Result<TreeReport, crate::error::Error> {
+        let snapshot = self.remote_snapshot(remote_dir).await?;
+        let depth = |path: &&String| std::cmp::Reverse(path.matches('/').count());
+        let mut files: Vec<_> = snapshot.files.keys().chain(&snapshot.others).collect();
+        files.sort_by_key(depth);
+        let mut directories: Vec<_> = snapshot.directories.iter().collect();
+        directories.sort_by_key(depth);
+
+        let mut report = TreeReport::default();
+        for remote_path in &snapshot.unsafe_paths {
+            let reason = "The name could escape the directory it is listed on.".to_string();
+            report.push(remote_path, Path::new(""), FileStatus::Skipped(reason));
+        }
+        for (relative_dir, line) in snapshot.unparsed {
+            let remote_path = join_remote(remote_dir, &relative_dir);
+            report.push(&remote_path, Path::new(""), FileStatus::Skipped(line));
+        }
+        let root = String::new();
+        let entries = files
+            .into_iter()
+            .map(|file| (file, false))
+            .chain(directories.into_iter().map(|directory| (directory, true)))
+            .chain(std::iter::once((&root, true)));
+        for (relative_path, is_directory) in entries {
+            let remote_path = join_remote(remote_dir, relative_path);
+            let result = if is_directory {
+                self.remove_directory(&remote_path).await
+            } else {
+                self.delete_file(&remote_path).await
+            };
+            let failed = result.is_err();
+            let status = match result {
+                Ok(()) => FileStatus::Deleted,
+                Err(error) => FileStatus::Failed(error),
+            };
+            report.push(&remote_path, Path::new(""), status);
+            if failed && stop_on_error {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn plan_sync(
+        &mut self,
+        local_dir: &Path,
+        remote_dir: &str,
+        direction: SyncDirection,
+        options: SyncOptions,
+        report: &mut TreeReport,
+    ) -> Result<SyncPlan, crate::error::Error> {
+        self.binary().await?;
+        let checksum = options.comparison == Comparison::Checksum && self.select_md5().await?;
+        let (source, destination) = match direction {
+            SyncDirection::Download => {
+                let source = self.remote_snapshot(remote_dir).await?;
+                let destination = match tokio::fs::metadata(local_dir).await {
+                    Ok(_) => Some(local_snapshot(local_dir).await?),
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(error) => return Err(error.into()),
+                };
+                (source, destination)
+            }
+            SyncDirection::Upload => {
+                let source = local_snapshot(local_dir).await?;
+                let destination = match self.list_root(remote_dir).await? {
+                    Some(listing) => Some(self.snapshot_from(remote_dir, listing).await?),
+                    None => None,
+                };
+                (source, destination)
+            }
+        };
+        let remote = match direction {
+            SyncDirection::Download => Some(&source),
+            SyncDirection::Upload => destination.as_ref(),
+        };
+        for (relative_dir, line) in remote.iter().flat_map(|remote| &remote.unparsed) {
+            let remote_path = join_remote(remote_dir, relative_dir);
+            let local_path = join_local(local_dir, relative_dir);
+            report.push(&remote_path, &local_path, FileStatus::Skipped(line.clone()));
+        }
+
+        let mut plan = SyncPlan {
+            direction,
+            steps: Vec::new(),
+        };
+        let paths = |relative: &str| {
+            (
+                join_remote(remote_dir, relative),
+                join_local(local_dir, relative),
+            )
+        };
+        let destination = match destination {
+            Some(destination) => destination,
+            None => {
+                let (remote_path, local_path) = paths("");
+                plan.push(SyncAction::CreateDirectory, remote_path, local_path);
+                Snapshot::default()
+            }
+        };
+
+        for directory in source.directories.difference(&destination.directories) {
+            let (remote_path, local_path) = paths(directory);
+            plan.push(SyncAction::CreateDirectory, remote_path, local_path);
+        }
+        for (file, source_info) in &source.files {
+            let (remote_path, local_path) = paths(file);
+            let action = match destination.files.get(file) {
+                None => SyncAction::TransferNew,
+                Some(destination_info) => {
+                    let changed = self
+                        .has_changed(
+                            &remote_path,
+                            &local_path,
+                            source_info,
+                            destination_info,
+                            checksum,
+                        )
+                        .await?;
+                    if changed {
+                        SyncAction::TransferChanged
+                    } else {
+                        SyncAction::Unchanged
+                    }
+                }
+            };
+            plan.push(action, remote_path, local_path);
+        }
+        if options.delete {
+            for file in destination.files.keys() {
+                if !source.files.contains_key(file) {
+                    let (remote_path, local_path) = paths(file);
+                    plan.push(SyncAction::Delete, remote_path, local_path);
+                }
+            }
+            // Nested directories are sorted after their parents.
+            let missing: Vec<_> = destination
+                .directories
+                .difference(&source.directories)
+                .collect();
+            for directory in missing.into_iter().rev() {
+                let (remote_path, local_path) = paths(directory);
+                plan.push(SyncAction::DeleteDirectory, remote_path, local_path);
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Carry out a step of a sync, returning the status to report, if any.
+    async fn run_sync_step(
+        &mut self,
+        step: &SyncStep,
+        direction: SyncDirection,
+    ) -> Option<FileStatus> {
+        let (remote_path, local_path) = (step.remote_path.as_str(), step.local_path.as_path());
+        let download = direction == SyncDirection::Download;
+        let result = match step.action {
+            SyncAction::CreateDirectory if download => tokio::fs::create_dir_all(local_path)
+                .await
+                .map_err(Into::into),
+            SyncAction::CreateDirectory => self.make_directory(remote_path).await,
+            SyncAction::TransferNew | SyncAction::TransferChanged => {
+                let transferred = if download {
+                    self.download_tree_file(remote_path, local_path).await
+                } else {
+                    self.upload_tree_file(local_path, remote_path).await
+                };
+                return Some(match transferred {
+                    Ok(bytes) => FileStatus::Transferred(bytes),
+                    Err(error) => FileStatus::Failed(error),
+                });
+            }
+            SyncAction::Unchanged => return Some(FileStatus::Skipped("Unchanged.".to_string())),
+            SyncAction::Delete if download => {
+                tokio::fs::remove_file(local_path).await.map_err(Into::into)
+            }
+            SyncAction::Delete => self.delete_file(remote_path).await,
+            SyncAction::DeleteDirectory if download => {
+                tokio::fs::remove_dir(local_path).await.map_err(Into::into)
+            }
+            SyncAction::DeleteDirectory => self.remove_directory(remote_path).await,
+        };
+
+        match (result, step.action) {
+            (Err(error), _) => Some(FileStatus::Failed(error)),
+            (Ok(()), SyncAction::CreateDirectory) => None,
+            (Ok(()), _) => Some(FileStatus::Deleted),
+        }
+    }
+
+    /// Compare a file on the source with the file on the destination.
+    async fn has_changed(
+        &mut self,
+        remote_path: &str,
+        local_path: &Path,
+        source: &FileInfo,
+        destination: &FileInfo,
+        checksum: bool,
+    ) -> Result<bool, crate::error::Error> {
+        if let (Some(source_size), Some(destination_size)) = (source.size, destination.size) {
+            if source_size != destination_size {
+                return Ok(true);
+            }
+        }
+        if checksum {
+            match self.hash(remote_path).await {
+                Ok(remote_hash) => return Ok(remote_hash.value != local_md5(local_path).await?),
+                // Files the server fails to hash, such as with a 550 reply,
+                // are compared by modification time instead.
+                Err(error @ crate::error::Error::UnexpectedReply(_))
+                | Err(error @ crate::error::Error::SerializationFailed(_)) => {
+                    info!("Comparing {} by modification time: {}", remote_path, error)
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        match (source.modified, destination.modified) {
+            // Listings may round the time down to the second.
+            (Some(source_time), Some(destination_time)) => {
+                Ok(source_time > destination_time + Duration::from_secs(1))
+            }
+            _ => Ok(source.size.is_none() || destination.size.is_none()),
+        }
+    }
+
+    /// Select MD5 for the HASH command, returning whether the server supports it.
+    async fn select_md5(&mut self) -> Result<bool, crate::error::Error> {
+        let supported = match &self.features().await?.hash_algorithms {
+            Some(algorithms) => algorithms.iter().any(|algorithm| algorithm == "md5"),
+            None => false,
+        };
+        if supported {
+            self.set_hash_algorithm("MD5").await?;
+        }
+        Ok(supported)
+    }
+
+    /// List every file and directory under a remote directory.
+    async fn remote_snapshot(&mut self, root: &str) -> Result<Snapshot, crate::error::Error> {
+        let listing = self.list_directory(root).await?;
+        self.snapshot_from(root, listing).await
+    }
+
+    /// List every file and directory under a remote directory, whose entries are known.
+    async fn snapshot_from(
+        &mut self,
+        root: &str,
+        root_listing: Listing,
+    ) -> Result<Snapshot, crate::error::Error> {
+        let mut snapshot = Snapshot::default();
+        let mut root_listing = Some(root_listing);
+        let mut pending = VecDeque::new();
+        pending.push_back(String::new());
+        while let Some(relative_dir) = pending.pop_front() {
+            let listing = match root_listing.take() {
+                Some(listing) => listing,
+                None => {
+                    self.list_directory(&join_remote(root, &relative_dir))
+                        .await?
+                }
+            };
+            for line in listing.unparsed {
+                snapshot.unparsed.push((relative_dir.clone(), line));
+            }
+            for entry in listing.entries {
+                if !is_safe_name(&entry.name) {
+                    let dir = join_remote(root, &relative_dir);
+                    snapshot.unsafe_paths.push(join_remote(&dir, &entry.name));
+                    continue;
+                }
+                let relative_path = join_relative(&relative_dir, &entry.name);
+                if entry.is_directory() {
+                    snapshot.directories.insert(relative_path.clone());
+                    pending.push_back(relative_path);
+                } else if entry.is_file() {
+                    let info = FileInfo {
+                        size: entry.size,
+                        modified: entry.modified,
+                    };
+                    snapshot.files.insert(relative_path, info);
+                } else {
+                    snapshot.others.insert(relative_path);
+                }
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    async fn upload_tree_file(
+        &mut self,
+        local_path: &Path,
+        remote_path: &str,
+    ) -> Result<u64, crate::error::Error> {
+        let file = tokio::fs::File::open(local_path).await?;
+        self.store_from(remote_path, file).await
+    }
+
+    async fn download_tree_file(
+        &mut self,
+        remote_path: &str,
+        local_path: &Path,
+    ) -> Result<u64, crate::error::Error> {
+        let mut file = tokio::fs::File::create(local_path).await?;
+        let downloaded = self.retrieve_to(remote_path, &mut file).await?;
+        file.flush().await?;
+        Ok(downloaded)
+    }
+
+    /// List the root of a remote tree, returning None if it does not exist, which
+    /// servers report with a 550 reply, or a 501 reply to MLSD as described on RFC3659.
+    /// Other errors are returned.
+    async fn list_root(&mut self, root: &str) -> Result<Option<Listing>, crate::error::Error> {
+        let mlsd = self.features().await?.mlst_facts.is_some();
+        match self.list_directory(root).await {
+            Ok(listing) => Ok(Some(listing)),
+            Err(crate::error::Error::UnexpectedReply(reply))
+                if reply.code == 550 || (reply.code == 501 && mlsd) =>
+            {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// List the entries of a remote directory with MLSD when supported by the
+    /// server, otherwise with LIST, leaving out the directory itself and its parent.
+    /// The LIST lines that could not be parsed are kept on the listing.
+    async fn list_directory(&mut self, path: &str) -> Result<Listing, crate::error::Error> {
+        let mut listing = if self.features().await?.mlst_facts.is_some() {
+            Listing {
+                entries: self.mlsd(path).await?,
+                unparsed: Vec::new(),
+            }
+        } else {
+            self.list_entries(path).await?
+        };
+        listing.entries.retain(|entry| {
+            !matches!(
+                entry.kind,
+                EntryKind::CurrentDirectory | EntryKind::ParentDirectory
+            ) && entry.name != "."
+                && entry.name != ".."
+        });
+
+        Ok(listing)
+    }
+}
+
+/// The entries of a local directory, with their names if valid UTF-8.
+type LocalEntries = Vec<(Option<String>, PathBuf)>;
+
+/// Read the entries of a local directory, sorted by path.
+async fn read_local_dir(dir: &Path) -> Result<LocalEntries, crate::error::Error> {
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        entries.push((entry.file_name().into_string().ok(), entry.path()));
+    }
+    entries.sort_by(|left, right| left.1.cmp(&right.1));
+    Ok(entries)
+}
+
+/// List every file and directory under a local directory. Symbolic links are
+/// left out, so a link cycle can not loop the listing.
+async fn local_snapshot(root: &Path) -> Result<Snapshot, crate::error::Error> {
+    let mut snapshot = Snapshot::default();
+    let mut pending = VecDeque::new();
+    pending.push_back(String::new());
+    while let Some(relative_dir) = pending.pop_front() {
+        for (name, path) in read_local_dir(&join_local(root, &relative_dir)).await? {
+            // Names that are not valid UTF-8 can not be sent to the server.
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+            let relative_path = join_relative(&relative_dir, &name);
+            let metadata = tokio::fs::symlink_metadata(&path).await?;
+            if metadata.file_type().is_symlink() {
+                continue;
+            } else if metadata.is_dir() {
+                snapshot.directories.insert(relative_path.clone());
+                pending.push_back(relative_path);
+            } else if metadata.is_file() {
+                let info = FileInfo {
+                    size: Some(metadata.len()),
+                    modified: metadata.modified().ok(),
+                };
+                snapshot.files.insert(relative_path, info);
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
+
+/// Get the MD5 checksum of a local file.
+async fn local_md5(path: &Path) -> Result<String, crate::error::Error> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut digest = Md5::new();
+    let mut buffer = vec![0; 8192];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(format!("{:x}", digest.finalize()));
+        }
+        digest.update(&buffer[..read]);
+    }
+}
+
+/// Join a name to a path relative to the root of a tree.
+fn join_relative(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Join a path relative to the root of a tree to a local directory path.
+fn join_local(dir: &Path, relative: &str) -> PathBuf {
+    relative
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .fold(dir.to_path_buf(), |path, component| path.join(component))
+}
+
+/// Join a name to a remote directory path.
+fn join_remote(dir: &str, name: &str) -> String {
+    if name.is_empty() {
+        dir.to_string()
+    } else if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), name)
+    }
+}
+
+/// Check that a listed name can not escape the directory it is listed on.
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
@@ -4,11 +4,26 @@
 //! Tests that start with test_ are run with
 //! external FTP servers, the others are run
 //! with a local dockerize server that you should start.
+use ftp_client::checksum::FileHash;
+use ftp_client::client::{
+    Client as AsyncClient, ClientMode, ReconnectPolicy, ServerResponse, Timeouts,
+};
 use ftp_client::error::Error as FtpError;
+use ftp_client::features::Features;
+use ftp_client::listing::{parse_timestamp, Entry, EntryKind, Listing};
+use ftp_client::pool::Pool;
+use ftp_client::progress::Progress;
+use ftp_client::rustls::ClientConfig;
 use ftp_client::sync::Client as SyncClient;
+use ftp_client::throttle::ThrottledStream;
+use ftp_client::timeout::TimeoutStream;
+use ftp_client::tree::{Comparison, FileStatus, SyncAction, SyncDirection, SyncOptions};
 use once_cell::sync::OnceCell;
-use std::io::Read;
-use std::sync::Mutex;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+use tokio::runtime::Runtime;
 
 #[test]
 fn test_name_listing() -> Result<(), FtpError> {
@@ -20,6 +35,15 @@ fn test_name_listing() -> Result<(), FtpError> {
     Ok(())
 }
 
+#[test]
+fn connection_with_port() -> Result<(), FtpError> {
+    let mut client =
+        SyncClient::connect_with_port(&get_local_server_hostname(), 21, "user", "user")?;
+    client.noop()?;
+
+    Ok(())
+}
+
 #[test]
 fn test_pwd() -> Result<(), FtpError> {
     let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
@@ -90,6 +114,295 @@ fn test_noop() -> Result<(), FtpError> {
     client.noop()
 }
 
+#[test]
+fn test_multiline_reply() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    // The full help listing spans many lines, the client should still
+    // be in sync with the server afterwards.
+    client.help(None)?;
+    client.noop()
+}
+
+#[test]
+fn multiline_reply_parsing() {
+    let response =
+        ServerResponse::parse("211-Features:\r\n MDTM\r\n SIZE\r\n211-Extra\r\n211 End\r\n");
+    assert!(response.is_multiline());
+    assert_eq!(response.status_code().code, 211);
+    assert_eq!(response.message(), "Features:");
+    assert_eq!(
+        response.lines(),
+        &["Features:", " MDTM", " SIZE", "Extra", "End"]
+    );
+
+    let response = ServerResponse::parse("200 Command okay.\r\n");
+    assert!(!response.is_multiline());
+    assert_eq!(response.message(), "Command okay.");
+}
+
+#[test]
+fn test_features() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    let features = client.features()?;
+    assert!(features.size);
+    assert!(features.mdtm);
+    assert!(features.rest_stream);
+    assert!(features.mlst_facts.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn features_parsing() {
+    let response = ServerResponse::parse(
+        "211-Features:\r\n AUTH TLS\r\n MLST type*;size*;modify*;UNIX.mode;\r\n REST STREAM\r\n SIZE\r\n UTF8\r\n HASH SHA-256;MD5*\r\n LANG EN*\r\n211 End\r\n",
+    );
+    let features = Features::parse(&response);
+    assert_eq!(features.auth, vec!["TLS".to_string()]);
+    assert_eq!(
+        features.mlst_facts,
+        Some(vec![
+            "type".to_string(),
+            "size".to_string(),
+            "modify".to_string(),
+            "unix.mode".to_string(),
+        ])
+    );
+    assert!(features.rest_stream && features.size && features.utf8);
+    assert!(!features.mdtm);
+    assert_eq!(
+        features.hash_algorithms,
+        Some(vec!["sha-256".to_string(), "md5".to_string()])
+    );
+    assert!(features.supports("LANG"));
+    assert!(!features.supports("LAN"));
+}
+
+#[test]
+fn test_machine_listing() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    let entries = client.mlsd("/pub")?;
+    let example = entries
+        .iter()
+        .find(|entry| entry.name == "example")
+        .unwrap();
+    let sample = entries
+        .iter()
+        .find(|entry| entry.name == "sample.txt")
+        .unwrap();
+    assert!(example.is_directory());
+    assert!(sample.is_file());
+    assert_eq!(sample.size, Some(0));
+
+    let cat = client.mlst("/cat.png")?;
+    assert!(cat.is_file());
+    assert_eq!(cat.size, Some(29579));
+    assert!(cat.modified.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn machine_listing_parsing() -> Result<(), FtpError> {
+    let entry = Entry::parse_mlsx(
+        "type=file;Size=1024;modify=20200229235958.5;perm=adfrw;unique=801g2;UNIX.mode=0644; my file.txt",
+    )?;
+    assert_eq!(entry.name, "my file.txt");
+    assert_eq!(entry.kind, EntryKind::File);
+    assert_eq!(entry.size, Some(1024));
+    assert_eq!(
+        entry.modified,
+        Some(UNIX_EPOCH + Duration::from_millis(1_583_020_798_500))
+    );
+    assert_eq!(entry.permissions.as_deref(), Some("adfrw"));
+    assert_eq!(entry.unique.as_deref(), Some("801g2"));
+    assert_eq!(entry.unix_mode, Some(0o644));
+
+    let entry = Entry::parse_mlsx("type=OS.unix=slink:/some/target; link")?;
+    assert_eq!(entry.kind, EntryKind::Link);
+    assert_eq!(entry.link_target.as_deref(), Some("/some/target"));
+    assert_eq!(
+        Entry::parse_mlsx("type=cdir; .")?.kind,
+        EntryKind::CurrentDirectory
+    );
+    assert!(Entry::parse_mlsx("type=file;size=abc; file").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parsed_listing() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    let listing = client.list_entries("/pub")?;
+    assert!(listing.unparsed.is_empty());
+    let example = listing.entries.iter().find(|entry| entry.name == "example");
+    assert!(example.unwrap().is_directory());
+
+    Ok(())
+}
+
+#[test]
+fn unix_listing_parsing() {
+    let listing = Listing::parse(
+        "total 12\r\n\
+         drwxr-xr-x   2 owner group      4096 Mar 01  2020 some dir\r\n\
+         -rw-r--r--   1 owner group      1024 Feb 29  2020 file.txt\r\n\
+         lrwxrwxrwx   1 owner           7 Jan 01  2019 link -> target\r\n\
+         -rw-r--r--   1 owner group      1024 Feb 30  2020 invalid.txt\r\n\
+         crw-rw-rw-   1 root  root   1,   3 Jan 01  2019 null\r\n\
+         -rwsr-xr-t   1 owner group         1 Jan 01 12:00 recent\r\n\
+         this is not a listing line\r\n",
+    );
+    assert_eq!(
+        listing.unparsed,
+        vec![
+            "-rw-r--r--   1 owner group      1024 Feb 30  2020 invalid.txt",
+            "this is not a listing line"
+        ]
+    );
+    let entries = listing.entries;
+    assert_eq!(entries.len(), 5);
+
+    assert_eq!(entries[0].name, "some dir");
+    assert_eq!(entries[0].kind, EntryKind::Directory);
+    assert_eq!(entries[0].unix_mode, Some(0o755));
+
+    assert_eq!(entries[1].name, "file.txt");
+    assert_eq!(entries[1].size, Some(1024));
+    assert_eq!(
+        entries[1].modified,
+        Some(UNIX_EPOCH + Duration::from_secs(1_582_934_400))
+    );
+
+    assert_eq!(entries[2].kind, EntryKind::Link);
+    assert_eq!(entries[2].name, "link");
+    assert_eq!(entries[2].link_target.as_deref(), Some("target"));
+
+    assert_eq!(entries[3].name, "null");
+    assert_eq!(entries[3].size, None);
+
+    assert_eq!(entries[4].unix_mode, Some(0o5755));
+    assert!(entries[4].modified.is_some());
+}
+
+#[test]
+fn dos_listing_parsing() {
+    let listing = Listing::parse(
+        "01-16-20  02:30PM       <DIR>          some dir\r\n\
+         12-31-1999  12:05AM              2048 file.txt\r\n",
+    );
+    assert!(listing.unparsed.is_empty());
+    let entries = listing.entries;
+
+    assert_eq!(entries[0].name, "some dir");
+    assert!(entries[0].is_directory());
+    assert_eq!(
+        entries[0].modified,
+        Some(UNIX_EPOCH + Duration::from_secs(1_579_185_000))
+    );
+
+    assert_eq!(entries[1].name, "file.txt");
+    assert_eq!(entries[1].size, Some(2048));
+    assert_eq!(
+        entries[1].modified,
+        Some(UNIX_EPOCH + Duration::from_secs(946_598_700))
+    );
+}
+
+#[test]
+fn test_size() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+    assert_eq!(client.size("/cat.png")?, 29579);
+    assert!(client.size("/does-not-exist.png").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_modified() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    let modified = client.modified("/cat.png")?;
+    assert!(modified > UNIX_EPOCH);
+    assert!(modified < SystemTime::now());
+
+    Ok(())
+}
+
+#[test]
+fn timestamp_parsing() {
+    assert_eq!(
+        parse_timestamp("19980615100045"),
+        Some(UNIX_EPOCH + Duration::from_secs(897_904_845))
+    );
+    assert_eq!(
+        parse_timestamp("19980615100045.014"),
+        Some(UNIX_EPOCH + Duration::from_millis(897_904_845_014))
+    );
+    assert_eq!(parse_timestamp("19981315100045"), None);
+    assert_eq!(parse_timestamp("20200230000000"), None);
+    assert_eq!(parse_timestamp("19000229000000"), None);
+    assert_eq!(parse_timestamp("19990431000000"), None);
+    assert_eq!(parse_timestamp("1998061510"), None);
+}
+
+#[test]
+fn file_hash_parsing() {
+    assert_eq!(
+        FileHash::parse("MD5 0-1024 0123456789ABCDEF0123456789abcdef file.txt").unwrap(),
+        FileHash {
+            algorithm: "MD5".to_string(),
+            value: "0123456789abcdef0123456789abcdef".to_string(),
+        }
+    );
+    assert_eq!(
+        FileHash::parse("sha-1 0-4 da39a3ee5e6b4b0d3255bfef95601890afd80709 a file")
+            .unwrap()
+            .algorithm,
+        "SHA-1"
+    );
+    assert!(FileHash::parse("MD5 0-1024 not-a-checksum file.txt").is_err());
+    assert!(FileHash::parse("MD5").is_err());
+}
+
+#[test]
+fn test_secure_connection() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect_secure("test.rebex.net", "demo", "password")?;
+    client.noop()
+}
+
+#[test]
+fn test_implicit_secure_connection() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect_implicit_secure("test.rebex.net", "demo", "password")?;
+    client.noop()
+}
+
+#[test]
+fn test_secure_file_retrieval() -> Result<(), FtpError> {
+    let mut client = SyncClient::connect_secure("test.rebex.net", "demo", "password")?;
+    let readme = client.retrieve_file("/readme.txt")?;
+    assert!(!readme.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn secure_connection_to_ip_address() {
+    // Nothing listens on the port, so reaching it would fail with an IO error.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .unwrap()
+        .port() as u32;
+    let config = Arc::new(ClientConfig::new());
+
+    let explicit =
+        SyncClient::connect_secure_with_config("127.0.0.1", port, config.clone(), "user", "user");
+    assert!(matches!(explicit, Err(FtpError::TlsError(_))));
+    let implicit =
+        SyncClient::connect_implicit_secure_with_config("127.0.0.1", port, config, "user", "user");
+    assert!(matches!(implicit, Err(FtpError::TlsError(_))));
+}
+
 #[test]
 fn test_help() -> Result<(), FtpError> {
     let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
@@ -200,6 +513,994 @@ fn binary_transfer() -> Result<(), FtpError> {
     Ok(())
 }
 
+#[test]
+fn active_mode() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.set_mode(ClientMode::Active);
+    assert_eq!(
+        vec!["example".to_string(), "sample.txt".to_string(),],
+        client.list_names("/pub/")?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn active_mode_other_host() -> Result<(), FtpError> {
+    let mut data_port = 0;
+    let (port, server) = scripted_server(1, move |_, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match command {
+            "TYPE I" => reply("200 Type set to I."),
+            port if port.starts_with("PORT ") => {
+                let numbers: Vec<u16> = port[5..].split(',').map(|n| n.parse().unwrap()).collect();
+                data_port = numbers[4] * 256 + numbers[5];
+                reply("200 PORT command successful.")
+            }
+            "RETR file.txt" => {
+                // Another host connects first, only the server connection is accepted.
+                let mut other = std::net::TcpStream::connect(("::1", data_port)).unwrap();
+                other.write_all(b"other").unwrap();
+                let mut data = std::net::TcpStream::connect(("127.0.0.1", data_port)).unwrap();
+                data.write_all(b"data").unwrap();
+                vec![
+                    Reply::Line("150 Opening data connection.".to_string()),
+                    Reply::Line("226 Transfer complete.".to_string()),
+                ]
+            }
+            _ => reply("500 Unexpected command."),
+        })
+    });
+
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    client.set_mode(ClientMode::Active);
+    // A dual stack listener, so hosts on IPV6 can connect too.
+    client.set_active_address(std::net::Ipv6Addr::UNSPECIFIED.into());
+    client.binary()?;
+    assert_eq!(client.retrieve_file("file.txt")?, b"data");
+    drop(client);
+    server.join().unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn resume_download() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+    let local_path = std::env::temp_dir().join("ftp-client-resume-download.png");
+    std::fs::write(&local_path, &reference_bytes[..10000]).unwrap();
+
+    let downloaded = client.resume_download("/cat.png", &local_path)?;
+    assert_eq!(downloaded, reference_bytes.len() as u64 - 10000);
+    assert_eq!(std::fs::read(&local_path).unwrap(), reference_bytes);
+    // Nothing is left to download on a complete file.
+    assert_eq!(client.resume_download("/cat.png", &local_path)?, 0);
+    std::fs::remove_file(&local_path).unwrap();
+
+    let tail = client.retrieve_file_from("/cat.png", 29000)?;
+    assert_eq!(tail, &reference_bytes[29000..]);
+    assert!(client.retrieve_file_from("/cat.png", 30000).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn restart_after_data_connection() -> Result<(), FtpError> {
+    let (port, server) = scripted_server(1, |_, command| {
+        login_replies(command).unwrap_or_else(|| match command {
+            "REST 4" => vec![Reply::Line("350 Restarting at 4.".to_string())],
+            "RETR file.txt" => vec![
+                Reply::Line("150 Opening data connection.".to_string()),
+                Reply::Data(b"tail".to_vec()),
+                Reply::Line("226 Transfer complete.".to_string()),
+            ],
+            _ => vec![Reply::Line("500 Unexpected command.".to_string())],
+        })
+    });
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    assert_eq!(client.retrieve_file_from("file.txt", 4)?, b"tail");
+    drop(client);
+
+    // Servers may reset the offset on EPSV, so REST comes right before RETR.
+    let commands = server.join().unwrap().remove(0);
+    assert_eq!(
+        commands[commands.len() - 3..],
+        ["EPSV", "REST 4", "RETR file.txt"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn streaming_retrieval() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+
+    let mut cat_file = Vec::new();
+    let downloaded = client.retrieve_to("/cat.png", &mut cat_file)?;
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+    assert_eq!(downloaded, reference_bytes.len() as u64);
+    assert_eq!(cat_file, reference_bytes);
+
+    Ok(())
+}
+
+#[test]
+fn streaming_upload() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+    let cat_file = std::fs::File::open("res/cat.png").unwrap();
+    let uploaded = client.store_from("/streamed_cat.png", cat_file)?;
+    assert_eq!(uploaded, reference_bytes.len() as u64);
+    assert_eq!(client.retrieve_file("/streamed_cat.png")?, reference_bytes);
+
+    let appended = client.append_from("/streamed_cat.png", &b"tail"[..])?;
+    assert_eq!(appended, 4);
+    assert_eq!(
+        client.size("/streamed_cat.png")?,
+        reference_bytes.len() as u64 + 4
+    );
+    client.delete_file("/streamed_cat.png")?;
+
+    Ok(())
+}
+
+#[test]
+fn transfer_handles() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+    let mut writer = client.open_write("/handle_cat.png")?;
+    writer.write_all(&reference_bytes).unwrap();
+    writer.finish()?;
+
+    let mut reader = client.open_read("/handle_cat.png")?;
+    let mut cat_file = Vec::new();
+    reader.read_to_end(&mut cat_file).unwrap();
+    reader.finish()?;
+    assert_eq!(cat_file, reference_bytes);
+
+    // The final reply of a dropped handle is read before the next command.
+    let mut reader = client.open_read("/handle_cat.png")?;
+    let mut header = [0; 8];
+    reader.read_exact(&mut header).unwrap();
+    drop(reader);
+    assert_eq!(header, reference_bytes[..8]);
+    client.delete_file("/handle_cat.png")?;
+
+    Ok(())
+}
+
+#[test]
+fn transfer_progress() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let callback_reports = reports.clone();
+    client.set_progress_callback(move |progress: &Progress| {
+        callback_reports
+            .lock()
+            .unwrap()
+            .push((progress.transferred(), progress.total()));
+    });
+
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+    let size = reference_bytes.len() as u64;
+    client.retrieve_file("/cat.png")?;
+    let downloaded = reports.lock().unwrap().drain(..).collect::<Vec<_>>();
+    assert!(downloaded.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert_eq!(downloaded.last(), Some(&(size, Some(size))));
+
+    client.retrieve_file_from("/cat.png", 29000)?;
+    let resumed = reports.lock().unwrap().drain(..).collect::<Vec<_>>();
+    assert!(resumed.iter().all(|report| report.0 > 29000));
+    assert_eq!(resumed.last(), Some(&(size, Some(size))));
+
+    client.store("/progress_cat.png", &reference_bytes)?;
+    let uploaded = reports.lock().unwrap().drain(..).collect::<Vec<_>>();
+    assert_eq!(uploaded.last(), Some(&(size, Some(size))));
+    client.clear_progress_callback();
+    client.delete_file("/progress_cat.png")?;
+    assert!(reports.lock().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn rate_limit() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+
+    // About 29KB at 20KB/s should take over a second each way.
+    client.set_rate_limit(20_000);
+    let start = std::time::Instant::now();
+    client.store("/throttled_cat.png", &reference_bytes)?;
+    assert!(start.elapsed() >= Duration::from_millis(1000));
+
+    client.set_upload_rate_limit(1_000_000);
+    let start = std::time::Instant::now();
+    let mut cat_file = Vec::new();
+    client.retrieve_to("/throttled_cat.png", &mut cat_file)?;
+    assert!(start.elapsed() >= Duration::from_millis(1000));
+    assert_eq!(cat_file, reference_bytes);
+
+    client.clear_rate_limit();
+    client.delete_file("/throttled_cat.png")?;
+
+    Ok(())
+}
+
+#[test]
+fn zero_rate_limit() {
+    let data = vec![0; 4096];
+    let mut runtime = Runtime::new().unwrap();
+    // A limit of 0 is no limit, so reads are not split in small chunks.
+    let mut conn = ThrottledStream::new(&data[..], Some(0));
+    let mut buffer = vec![0; 4096];
+    let read = runtime.block_on(conn.read(&mut buffer)).unwrap();
+    assert_eq!(read, data.len());
+}
+
+#[test]
+fn keepalive() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+
+    // Throttle the transfers so a few NOOP commands are sent during them.
+    client.set_rate_limit(20_000);
+    client.set_keepalive(Duration::from_millis(200));
+    client.store("/keepalive_cat.png", &reference_bytes)?;
+    assert_eq!(client.retrieve_file("/keepalive_cat.png")?, reference_bytes);
+
+    // Every NOOP reply was read, so the next replies are in order.
+    client.clear_keepalive();
+    client.clear_rate_limit();
+    assert_eq!(
+        client.size("/keepalive_cat.png")?,
+        reference_bytes.len() as u64
+    );
+    client.delete_file("/keepalive_cat.png")?;
+
+    Ok(())
+}
+
+#[test]
+fn keepalive_failed_transfer() -> Result<(), FtpError> {
+    let (port, server) = scripted_server(1, |_, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match command {
+            "NOOP" => reply("200 NOOP ok."),
+            "PWD" => reply("257 \"/\" is the current directory."),
+            "RETR file.txt" => vec![
+                Reply::Line("150 Opening data connection.".to_string()),
+                Reply::Wait(Duration::from_millis(500)),
+                Reply::Data(b"data".to_vec()),
+                Reply::Line("226 Transfer complete.".to_string()),
+            ],
+            _ => reply("500 Unexpected command."),
+        })
+    });
+
+    struct BrokenWriter;
+    impl Write for BrokenWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(ErrorKind::Other, "The disk is full."))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    client.set_keepalive(Duration::from_millis(100));
+    assert!(client.retrieve_to("file.txt", BrokenWriter).is_err());
+    // The replies to the transfer and to every NOOP are read before the next command.
+    assert_eq!(client.pwd()?, "\"/\" is the current directory.");
+    drop(client);
+
+    let commands = server.join().unwrap().remove(0);
+    assert!(commands.iter().any(|command| command == "NOOP"));
+
+    Ok(())
+}
+
+#[test]
+fn reconnect() -> Result<(), FtpError> {
+    let (port, server) = scripted_server(4, |connection, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match (connection, command) {
+            (_, "TYPE I") => reply("200 Type set to I."),
+            (_, "CWD /pub") => reply("250 Directory changed."),
+            (0, "NOOP") => vec![
+                Reply::Line("421 Too many connections.".to_string()),
+                Reply::Close,
+            ],
+            (1, "NOOP") => reply("200 NOOP ok."),
+            (1, "PWD") => vec![Reply::Close],
+            (_, "PWD") => reply("257 \"/pub\" is the current directory."),
+            (_, "QUIT") => vec![Reply::Line("221 Goodbye.".to_string()), Reply::Close],
+            (_, "DELE file.txt") => vec![
+                Reply::Line("421 Closing the connection.".to_string()),
+                Reply::Close,
+            ],
+            (_, "MKD dir") => reply("257 \"/pub/dir\" created."),
+            _ => reply("500 Unexpected command."),
+        })
+    });
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    client.set_reconnect_policy(ReconnectPolicy {
+        max_attempts: 2,
+        delay: Duration::from_millis(10),
+    });
+    client.binary()?;
+    client.cwd("/pub")?;
+
+    // The server replies 421 and closes the connection.
+    client.noop()?;
+    // The server drops the connection without replying.
+    assert_eq!(client.pwd()?, "\"/pub\" is the current directory.");
+    // Commands with side effects are not run again, but the next command reconnects.
+    assert!(client.delete_file("file.txt").is_err());
+    client.make_directory("dir")?;
+    // The session is not restored after a logout.
+    client.logout()?;
+    assert!(client.noop().is_err());
+    drop(client);
+
+    let session = ["USER user", "PASS user", "TYPE I", "CWD /pub"];
+    let commands = server.join().unwrap();
+    assert_eq!(commands[0][..4], session);
+    assert_eq!(commands[0][4..], ["NOOP"]);
+    for (commands, expected) in commands[1..].iter().zip([
+        &["NOOP", "PWD"][..],
+        &["PWD", "DELE file.txt"],
+        &["MKD dir", "QUIT"],
+    ]) {
+        assert_eq!(commands[..4], session);
+        assert_eq!(commands[4..], *expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn reconnect_after_reset() -> Result<(), FtpError> {
+    let (port, server) = scripted_server(2, |connection, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match (connection, command) {
+            (0, "MKD dir") => vec![Reply::Reset],
+            (_, "MKD dir") => reply("257 \"/dir\" created."),
+            _ => reply("500 Unexpected command."),
+        })
+    });
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    client.set_reconnect_policy(ReconnectPolicy {
+        max_attempts: 2,
+        delay: Duration::from_millis(10),
+    });
+
+    // A reset connection is lost, so the next command reconnects first.
+    let result = client.make_directory("dir");
+    assert!(
+        matches!(result, Err(FtpError::IoError(error)) if error.kind() == ErrorKind::ConnectionReset)
+    );
+    client.make_directory("dir")?;
+    drop(client);
+
+    let commands = server.join().unwrap();
+    assert_eq!(commands[0], ["USER user", "PASS user", "MKD dir"]);
+    assert_eq!(commands[1], ["USER user", "PASS user", "MKD dir"]);
+
+    Ok(())
+}
+
+#[test]
+fn abort_transfer() -> Result<(), FtpError> {
+    lock_server();
+    let mut runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut client = AsyncClient::connect(&get_local_server_hostname(), "user", "user").await?;
+        let mut data_connection = client.start_transfer("RETR cat.png").await?;
+        let mut partial = [0; 1024];
+        data_connection.read_exact(&mut partial).await?;
+        client.abort(data_connection).await?;
+
+        // The session must still be usable after the abort.
+        client.noop().await?;
+        let names = client.list_names("/pub/").await?;
+        assert_eq!(vec!["example".to_string(), "sample.txt".to_string()], names);
+
+        Ok::<(), FtpError>(())
+    })?;
+
+    // Transfers opened with handles are aborted the same way.
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+    let mut reader = client.open_read("cat.png")?;
+    let mut partial = [0; 1024];
+    reader.read_exact(&mut partial).unwrap();
+    reader.abort()?;
+    let mut writer = client.open_write("/aborted_cat.png")?;
+    writer.write_all(&partial).unwrap();
+    writer.abort()?;
+    client.noop()?;
+    if client
+        .list_names("/")?
+        .contains(&"aborted_cat.png".to_string())
+    {
+        client.delete_file("/aborted_cat.png")?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn timeouts() -> Result<(), FtpError> {
+    // A server that accepts connections but never replies.
+    let silent_server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = silent_server.local_addr().unwrap().port() as u32;
+    let timeouts = Timeouts {
+        reply: Some(Duration::from_millis(200)),
+        ..Timeouts::default()
+    };
+    let result = SyncClient::connect_with_timeouts("127.0.0.1", port, "user", "user", timeouts);
+    assert!(matches!(result, Err(FtpError::Timeout(_))));
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut listener =
+            tokio::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).await?;
+        let address = listener.local_addr()?;
+        let conn = tokio::net::TcpStream::connect(address).await?;
+        let (_idle_peer, _) = listener.accept().await?;
+
+        let mut conn = TimeoutStream::new(conn, Some(Duration::from_millis(200)));
+        let mut buffer = [0; 16];
+        let error: FtpError = conn.read(&mut buffer).await.unwrap_err().into();
+        assert!(matches!(error, FtpError::Timeout(_)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn connection_pool() -> Result<(), FtpError> {
+    lock_server();
+    let mut runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        let pool = Pool::new(2, || {
+            let hostname = get_local_server_hostname();
+            async move { AsyncClient::connect(&hostname, "user", "user").await }
+        });
+
+        let mut first = pool.get().await?;
+        let second = pool.get().await?;
+        first.noop().await?;
+        // Every client is in use, so the next one waits for a client to return.
+        let waiting = tokio::time::timeout(Duration::from_millis(200), pool.get()).await;
+        assert!(waiting.is_err());
+        drop(second);
+        assert_eq!(pool.idle_count(), 1);
+        let second = pool.get().await?;
+        assert_eq!(pool.idle_count(), 0);
+
+        // A client closed by the server fails the health check and is discarded.
+        first.logout().await?;
+        drop(first);
+        drop(second);
+        assert_eq!(pool.idle_count(), 2);
+        let mut first = pool.get().await?;
+        let mut second = pool.get().await?;
+        first.noop().await?;
+        second.noop().await?;
+        assert_eq!(pool.idle_count(), 0);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn segmented_download() -> Result<(), FtpError> {
+    lock_server();
+    let mut runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        let pool = Pool::new(3, || {
+            let hostname = get_local_server_hostname();
+            async move { AsyncClient::connect(&hostname, "user", "user").await }
+        });
+
+        let reference_bytes = std::fs::read("res/cat.png").unwrap();
+        let local_path = std::env::temp_dir().join("ftp-client-segmented-download.png");
+        let downloaded = pool.download_segmented("/cat.png", &local_path, 4).await?;
+        assert_eq!(downloaded, reference_bytes.len() as u64);
+        assert_eq!(std::fs::read(&local_path).unwrap(), reference_bytes);
+        std::fs::remove_file(&local_path).unwrap();
+
+        // Every client was left ready for new commands.
+        assert_eq!(pool.idle_count(), 3);
+        let mut client = pool.get().await?;
+        assert_eq!(client.size("/cat.png").await?, reference_bytes.len() as u64);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn segmented_download_restart() -> Result<(), FtpError> {
+    let mut offset = 0;
+    let (port, server) = scripted_server(1, move |_, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match command {
+            "TYPE I" | "NOOP" => reply("200 OK."),
+            "SIZE file.txt" => reply("213 10"),
+            "RETR file.txt" => vec![
+                Reply::Line("150 Opening data connection.".to_string()),
+                Reply::Data(b"0123456789"[offset..].to_vec()),
+                Reply::Line("226 Transfer complete.".to_string()),
+            ],
+            _ if command.starts_with("REST ") => {
+                offset = command[5..].parse().unwrap();
+                reply("350 Restarting.")
+            }
+            _ => reply("500 Unexpected command."),
+        })
+    });
+    let mut runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        let pool = Pool::new(1, move || async move {
+            AsyncClient::connect_with_port("127.0.0.1", port, "user", "user").await
+        });
+        let local_path = std::env::temp_dir().join("ftp-client-segmented-restart.txt");
+        assert_eq!(
+            pool.download_segmented("file.txt", &local_path, 2).await?,
+            10
+        );
+        assert_eq!(std::fs::read(&local_path).unwrap(), b"0123456789");
+        std::fs::remove_file(&local_path).unwrap();
+
+        Ok::<(), FtpError>(())
+    })?;
+
+    // Each segment sends REST right before RETR, after EPSV.
+    let transfers: Vec<_> = server.join().unwrap()[0]
+        .iter()
+        .filter(|command| !command.starts_with("TYPE") && !command.starts_with("NOOP"))
+        .skip_while(|command| !command.starts_with("EPSV"))
+        .cloned()
+        .collect();
+    let expected = [
+        "EPSV",
+        "REST 0",
+        "RETR file.txt",
+        "EPSV",
+        "REST 5",
+        "RETR file.txt",
+    ];
+    assert_eq!(transfers, expected);
+
+    Ok(())
+}
+
+#[test]
+fn tree_download() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.binary()?;
+    let reference_bytes = std::fs::read("res/cat.png").unwrap();
+    client.make_directory("/tree_download")?;
+    client.make_directory("/tree_download/nested")?;
+    client.store("/tree_download/cat.png", &reference_bytes)?;
+    client.store("/tree_download/nested/note.txt", b"nested note")?;
+
+    let local_dir = std::env::temp_dir().join("ftp-client-tree-download");
+    let report = client.download_tree("/tree_download", &local_dir)?;
+    assert!(report.is_success());
+    assert_eq!(report.transferred().count(), 2);
+    assert_eq!(
+        report.bytes_transferred(),
+        reference_bytes.len() as u64 + 11
+    );
+    assert_eq!(
+        std::fs::read(local_dir.join("cat.png")).unwrap(),
+        reference_bytes
+    );
+    assert_eq!(
+        std::fs::read(local_dir.join("nested").join("note.txt")).unwrap(),
+        b"nested note"
+    );
+
+    std::fs::remove_dir_all(&local_dir).unwrap();
+    client.delete_file("/tree_download/nested/note.txt")?;
+    client.delete_file("/tree_download/cat.png")?;
+    client.remove_directory("/tree_download/nested")?;
+    client.remove_directory("/tree_download")?;
+
+    Ok(())
+}
+
+#[test]
+fn tree_upload() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    let local_dir = std::env::temp_dir().join("ftp-client-tree-upload");
+    std::fs::create_dir_all(local_dir.join("nested").join("deeper")).unwrap();
+    std::fs::copy("res/cat.png", local_dir.join("cat.png")).unwrap();
+    std::fs::write(
+        local_dir.join("nested").join("deeper").join("note.txt"),
+        b"note",
+    )
+    .unwrap();
+    std::fs::write(local_dir.join("blocked"), b"blocked").unwrap();
+    // Links are skipped, so a link to the root does not loop the upload.
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&local_dir, local_dir.join("nested").join("loop")).unwrap();
+    // An existing remote directory is reused, a file can not replace a directory.
+    client.make_directory("/tree_upload")?;
+    client.make_directory("/tree_upload/nested")?;
+    client.make_directory("/tree_upload/blocked")?;
+
+    let report = client.upload_tree(&local_dir, "/tree_upload")?;
+    assert_eq!(report.transferred().count(), 2);
+    let failed: Vec<_> = report.failed().map(|file| &file.remote_path).collect();
+    assert_eq!(failed, vec!["/tree_upload/blocked"]);
+    #[cfg(unix)]
+    assert!(report
+        .skipped()
+        .any(|file| file.remote_path == "/tree_upload/nested/loop"));
+    assert_eq!(
+        client.retrieve_file("/tree_upload/cat.png")?,
+        std::fs::read("res/cat.png").unwrap()
+    );
+    assert_eq!(
+        client.retrieve_file("/tree_upload/nested/deeper/note.txt")?,
+        b"note"
+    );
+
+    std::fs::remove_dir_all(&local_dir).unwrap();
+    client.delete_file("/tree_upload/nested/deeper/note.txt")?;
+    client.delete_file("/tree_upload/cat.png")?;
+    client.remove_directory("/tree_upload/nested/deeper")?;
+    client.remove_directory("/tree_upload/nested")?;
+    client.remove_directory("/tree_upload/blocked")?;
+    client.remove_directory("/tree_upload")?;
+
+    Ok(())
+}
+
+#[test]
+fn tree_sync() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    let local_dir = std::env::temp_dir().join("ftp-client-tree-sync");
+    let mirror_dir = std::env::temp_dir().join("ftp-client-tree-sync-mirror");
+    std::fs::create_dir_all(local_dir.join("nested")).unwrap();
+    std::fs::write(local_dir.join("first.txt"), b"first").unwrap();
+    std::fs::write(local_dir.join("nested").join("second.txt"), b"second").unwrap();
+    // Links are left out, so a link to the root does not loop the sync.
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&local_dir, local_dir.join("nested").join("loop")).unwrap();
+    let actions = |report: &ftp_client::tree::SyncReport| -> Vec<SyncAction> {
+        report.plan.steps.iter().map(|step| step.action).collect()
+    };
+
+    let dry_run = SyncOptions {
+        dry_run: true,
+        ..SyncOptions::default()
+    };
+    let report = client.sync_tree(&local_dir, "/tree_sync", SyncDirection::Upload, dry_run)?;
+    assert_eq!(
+        actions(&report),
+        vec![
+            SyncAction::CreateDirectory,
+            SyncAction::CreateDirectory,
+            SyncAction::TransferNew,
+            SyncAction::TransferNew
+        ]
+    );
+    assert!(report.plan.to_string().contains("upload"));
+    assert!(report.files.files.is_empty());
+    assert!(client.list_names("/tree_sync").is_err());
+
+    let options = SyncOptions::default();
+    let report = client.sync_tree(&local_dir, "/tree_sync", SyncDirection::Upload, options)?;
+    assert!(report.files.is_success());
+    assert_eq!(report.files.transferred().count(), 2);
+    let report = client.sync_tree(&local_dir, "/tree_sync", SyncDirection::Upload, options)?;
+    assert!(report.plan.is_empty());
+
+    std::fs::write(local_dir.join("first.txt"), b"first, changed").unwrap();
+    std::fs::remove_dir_all(local_dir.join("nested")).unwrap();
+    // The sample server does not support HASH, so files are compared by size
+    // and time here, the checksums are covered by tree_sync_checksum.
+    let options = SyncOptions {
+        comparison: Comparison::Checksum,
+        delete: true,
+        dry_run: false,
+    };
+    let report = client.sync_tree(&local_dir, "/tree_sync", SyncDirection::Upload, options)?;
+    assert_eq!(
+        actions(&report),
+        vec![
+            SyncAction::TransferChanged,
+            SyncAction::Delete,
+            SyncAction::DeleteDirectory
+        ]
+    );
+    assert_eq!(report.files.deleted().count(), 2);
+    assert_eq!(
+        client.retrieve_file("/tree_sync/first.txt")?,
+        b"first, changed"
+    );
+
+    let report = client.sync_tree(&mirror_dir, "/tree_sync", SyncDirection::Download, options)?;
+    assert_eq!(report.files.transferred().count(), 1);
+    let report = client.sync_tree(&mirror_dir, "/tree_sync", SyncDirection::Download, options)?;
+    assert!(report.plan.is_empty());
+    assert_eq!(
+        std::fs::read(mirror_dir.join("first.txt")).unwrap(),
+        b"first, changed"
+    );
+
+    std::fs::remove_dir_all(&local_dir).unwrap();
+    std::fs::remove_dir_all(&mirror_dir).unwrap();
+    client.delete_file("/tree_sync/first.txt")?;
+    client.remove_directory("/tree_sync")?;
+
+    Ok(())
+}
+
+#[test]
+fn tree_sync_checksum() -> Result<(), FtpError> {
+    let (port, server) = scripted_server(1, |_, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        if command == "FEAT" {
+            return reply("211-Features:\r\n HASH SHA-1;MD5*\r\n211 End");
+        }
+        login_replies(command).unwrap_or_else(|| match command {
+            "TYPE I" => reply("200 Type set to I."),
+            "OPTS HASH MD5" => reply("200 MD5 selected."),
+            "LIST /tree" => vec![
+                Reply::Line("150 Opening data connection.".to_string()),
+                Reply::Data(
+                    b"-rw-r--r--   1 owner group  4 Jan 01  2020 changed.txt\r\n\
+                      -rw-r--r--   1 owner group  4 Jan 01  2020 same.txt\r\n\
+                      -rw-r--r--   1 owner group  4 Jan 01  2020 unhashed.txt\r\n"
+                        .to_vec(),
+                ),
+                Reply::Line("226 Transfer complete.".to_string()),
+            ],
+            "HASH /tree/changed.txt" => {
+                reply("213 MD5 0-4 0a5c7b01ae8c831958d3b5a0f0f9c571 changed.txt")
+            }
+            "HASH /tree/same.txt" => reply("213 MD5 0-4 51037a4a37730f52c8732586d3aaa316 same.txt"),
+            "HASH /tree/unhashed.txt" => reply("550 File not available."),
+            _ => reply("500 Unexpected command."),
+        })
+    });
+    let local_dir = std::env::temp_dir().join("ftp-client-tree-sync-checksum");
+    std::fs::create_dir_all(&local_dir).unwrap();
+    std::fs::write(local_dir.join("changed.txt"), b"new!").unwrap();
+    std::fs::write(local_dir.join("same.txt"), b"same").unwrap();
+    std::fs::write(local_dir.join("unhashed.txt"), b"file").unwrap();
+    let options = SyncOptions {
+        comparison: Comparison::Checksum,
+        delete: false,
+        dry_run: true,
+    };
+
+    // The local files are newer, but only the one whose checksum differs changed.
+    // The file the server fails to hash is compared by modification time.
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    let report = client.sync_tree(&local_dir, "/tree", SyncDirection::Upload, options)?;
+    let actions: Vec<_> = report.plan.steps.iter().map(|step| step.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            SyncAction::TransferChanged,
+            SyncAction::Unchanged,
+            SyncAction::TransferChanged
+        ]
+    );
+    drop(client);
+    server.join().unwrap();
+    std::fs::remove_dir_all(&local_dir).unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn tree_sync_missing_root() -> Result<(), FtpError> {
+    let mut listings = 0;
+    let (port, server) = scripted_server(1, move |_, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match command {
+            "TYPE I" => reply("200 Type set to I."),
+            "LIST /tree_sync" => {
+                listings += 1;
+                if listings == 1 {
+                    reply("451 Local error in processing.")
+                } else {
+                    reply("550 No such file or directory.")
+                }
+            }
+            _ => reply("500 Unexpected command."),
+        })
+    });
+    let local_dir = std::env::temp_dir().join("ftp-client-tree-sync-missing-root");
+    std::fs::create_dir_all(&local_dir).unwrap();
+    std::fs::write(local_dir.join("file.txt"), b"file").unwrap();
+    let dry_run = SyncOptions {
+        dry_run: true,
+        ..SyncOptions::default()
+    };
+
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    // Only a 550 on the root listing means the remote directory is missing.
+    let result = client.sync_tree(&local_dir, "/tree_sync", SyncDirection::Upload, dry_run);
+    assert!(matches!(result, Err(FtpError::UnexpectedReply(reply)) if reply.code == 451));
+    let report = client.sync_tree(&local_dir, "/tree_sync", SyncDirection::Upload, dry_run)?;
+    let actions: Vec<_> = report.plan.steps.iter().map(|step| step.action).collect();
+    assert_eq!(
+        actions,
+        vec![SyncAction::CreateDirectory, SyncAction::TransferNew]
+    );
+    drop(client);
+    server.join().unwrap();
+    std::fs::remove_dir_all(&local_dir).unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn tree_removal() -> Result<(), FtpError> {
+    lock_server();
+    let mut client = SyncClient::connect(&get_local_server_hostname(), "user", "user")?;
+    client.make_directory("/tree_removal")?;
+    client.make_directory("/tree_removal/nested")?;
+    client.make_directory("/tree_removal/nested/deeper")?;
+    client.store("/tree_removal/first.txt", b"first")?;
+    client.store("/tree_removal/nested/deeper/second.txt", b"second")?;
+
+    let report = client.remove_tree("/tree_removal", true)?;
+    assert!(report.is_success());
+    let deleted: Vec<_> = report
+        .deleted()
+        .map(|file| file.remote_path.as_str())
+        .collect();
+    assert_eq!(
+        deleted,
+        vec![
+            "/tree_removal/nested/deeper/second.txt",
+            "/tree_removal/first.txt",
+            "/tree_removal/nested/deeper",
+            "/tree_removal/nested",
+            "/tree_removal"
+        ]
+    );
+    assert!(client.list_names("/tree_removal").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tree_removal_special_entries() -> Result<(), FtpError> {
+    let (port, server) = scripted_server(1, |_, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match command {
+            "LIST /tree" => vec![
+                Reply::Line("150 Opening data connection.".to_string()),
+                Reply::Data(
+                    b"lrwxrwxrwx   1 owner group  6 Jan 01  2020 link -> target\r\n\
+                      prw-r--r--   1 owner group  0 Jan 01  2020 pipe\r\n\
+                      -rw-r--r--   1 owner group  4 Jan 01  2020 bad\\name\r\n\
+                      -rw-r--r--   1 owner group  4 Jan 01  2020 file.txt\r\n"
+                        .to_vec(),
+                ),
+                Reply::Line("226 Transfer complete.".to_string()),
+            ],
+            "DELE /tree/file.txt" | "DELE /tree/link" | "DELE /tree/pipe" => {
+                reply("250 File deleted.")
+            }
+            "RMD /tree" => reply("550 Directory not empty."),
+            _ => reply("500 Unexpected command."),
+        })
+    });
+
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    let report = client.remove_tree("/tree", false)?;
+    let paths = |files: Vec<&ftp_client::tree::FileReport>| -> Vec<String> {
+        files.iter().map(|file| file.remote_path.clone()).collect()
+    };
+    // Links and other entries are deleted, names that could escape the tree are reported.
+    assert_eq!(
+        paths(report.deleted().collect()),
+        vec!["/tree/file.txt", "/tree/link", "/tree/pipe"]
+    );
+    assert_eq!(paths(report.skipped().collect()), vec!["/tree/bad\\name"]);
+    assert_eq!(paths(report.failed().collect()), vec!["/tree"]);
+    drop(client);
+    server.join().unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn tree_unparsed_lines() -> Result<(), FtpError> {
+    let (port, server) = scripted_server(1, |_, command| {
+        let reply = |line: &str| vec![Reply::Line(line.to_string())];
+        login_replies(command).unwrap_or_else(|| match command {
+            "TYPE I" => reply("200 Type set to I."),
+            "LIST /tree" => vec![
+                Reply::Line("150 Opening data connection.".to_string()),
+                Reply::Data(
+                    b"-rw-r--r--   1 owner group  4 Jan 01  2020 file.txt\r\n\
+                      not a listing line\r\n"
+                        .to_vec(),
+                ),
+                Reply::Line("226 Transfer complete.".to_string()),
+            ],
+            "RETR /tree/file.txt" => vec![
+                Reply::Line("150 Opening data connection.".to_string()),
+                Reply::Data(b"file".to_vec()),
+                Reply::Line("226 Transfer complete.".to_string()),
+            ],
+            "DELE /tree/file.txt" => reply("250 File deleted."),
+            "RMD /tree" => reply("250 Directory removed."),
+            _ => reply("500 Unexpected command."),
+        })
+    });
+    let local_dir = std::env::temp_dir().join("ftp-client-tree-unparsed-lines");
+    let _ = std::fs::remove_dir_all(&local_dir);
+    let dry_run = SyncOptions {
+        dry_run: true,
+        ..SyncOptions::default()
+    };
+    let skipped = |files: Vec<&ftp_client::tree::FileReport>| -> Vec<(String, String)> {
+        files
+            .iter()
+            .map(|file| match &file.status {
+                FileStatus::Skipped(reason) => (file.remote_path.clone(), reason.clone()),
+                status => panic!("Unexpected status: {:?}", status),
+            })
+            .collect()
+    };
+    let expected = vec![("/tree".to_string(), "not a listing line".to_string())];
+
+    // Lines the listing parser does not understand are reported, not dropped.
+    let mut client = SyncClient::connect_with_port("127.0.0.1", port, "user", "user")?;
+    let report = client.download_tree("/tree", &local_dir)?;
+    assert_eq!(report.transferred().count(), 1);
+    assert_eq!(skipped(report.skipped().collect()), expected);
+    let report = client.sync_tree(&local_dir, "/tree", SyncDirection::Download, dry_run)?;
+    assert_eq!(skipped(report.files.skipped().collect()), expected);
+    let report = client.remove_tree("/tree", false)?;
+    assert_eq!(report.deleted().count(), 2);
+    assert_eq!(skipped(report.skipped().collect()), expected);
+    drop(client);
+    server.join().unwrap();
+    std::fs::remove_dir_all(&local_dir).unwrap();
+
+    Ok(())
+}
+
 /// Get the hostname for the local server.
 fn get_local_server_hostname() -> String {
     std::env::var("SERVER_HOSTNAME").expect("SERVER_HOSTNAME is not set.")
@@ -212,3 +1513,116 @@ fn lock_server() {
     let _guard = mutex.lock().expect("Could not lock server.");
     std::thread::sleep(std::time::Duration::from_millis(500));
 }
+
+/// A reply of the scripted server to a command.
+enum Reply {
+    /// A reply line, sent with a trailing CRLF.
+    Line(String),
+    /// Data sent on the data connection opened by the last EPSV, which is then closed.
+    Data(Vec<u8>),
+    /// Wait before the next reply.
+    Wait(Duration),
+    /// Close the control connection.
+    Close,
+    /// Reset the control connection, closing it without lingering.
+    Reset,
+}
+
+/// Run a local server answering each command with the replies of a script, called with
+/// the index of the control connection and the command. EPSV is handled by the server.
+/// Returns the port and the commands received on each connection, once they all closed.
+fn scripted_server<F>(
+    connections: usize,
+    script: F,
+) -> (u32, std::thread::JoinHandle<Vec<Vec<String>>>)
+where
+    F: FnMut(usize, &str) -> Vec<Reply> + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port() as u32;
+    let script = Arc::new(Mutex::new(script));
+    let server = std::thread::spawn(move || {
+        let handlers: Vec<_> = (0..connections)
+            .map(|index| {
+                let (conn, _) = listener.accept().unwrap();
+                let script = script.clone();
+                std::thread::spawn(move || serve_script(index, conn, script))
+            })
+            .collect();
+        handlers
+            .into_iter()
+            .map(|handler| handler.join().unwrap())
+            .collect()
+    });
+
+    (port, server)
+}
+
+/// Serve a control connection of the scripted server, returning the commands received.
+fn serve_script<F>(index: usize, conn: std::net::TcpStream, script: Arc<Mutex<F>>) -> Vec<String>
+where
+    F: FnMut(usize, &str) -> Vec<Reply>,
+{
+    use std::io::BufRead;
+
+    let mut writer = conn.try_clone().unwrap();
+    let mut reader = std::io::BufReader::new(conn);
+    let mut data_listener = None;
+    let mut commands = Vec::new();
+    writer.write_all(b"220 Scripted server ready.\r\n").unwrap();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return commands;
+        }
+        let command = line.trim_end().to_string();
+        commands.push(command.clone());
+        if command == "EPSV" {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let reply = format!("229 Entering Extended Passive Mode (|||{}|).\r\n", port);
+            writer.write_all(reply.as_bytes()).unwrap();
+            data_listener = Some(listener);
+            continue;
+        }
+
+        let replies = (script.lock().unwrap())(index, &command);
+        for reply in replies {
+            match reply {
+                Reply::Line(line) => writer.write_all(format!("{}\r\n", line).as_bytes()),
+                Reply::Data(data) => {
+                    let listener = data_listener.take().expect("No data connection.");
+                    let (mut data_conn, _) = listener.accept().unwrap();
+                    data_conn.write_all(&data)
+                }
+                Reply::Close => return commands,
+                Reply::Reset => {
+                    let runtime = Runtime::new().unwrap();
+                    runtime
+                        .enter(|| {
+                            let conn = tokio::net::TcpStream::from_std(writer.try_clone()?)?;
+                            conn.set_linger(Some(Duration::from_secs(0)))
+                        })
+                        .unwrap();
+                    return commands;
+                }
+                Reply::Wait(duration) => {
+                    std::thread::sleep(duration);
+                    Ok(())
+                }
+            }
+            .unwrap();
+        }
+    }
+}
+
+/// The replies of the scripted server to a login, or None for other commands.
+fn login_replies(command: &str) -> Option<Vec<Reply>> {
+    let reply = match command.split(' ').next() {
+        Some("USER") => "331 Password required.",
+        Some("PASS") => "230 Logged in.",
+        Some("FEAT") => "502 Not implemented.",
+        _ => return None,
+    };
+    Some(vec![Reply::Line(reply.to_string())])
+}